@@ -1,225 +1,472 @@
+use std::{fmt, io};
 use std::marker::PhantomData;
-use ff::{Field, PrimeField, PrimeFieldBits};
 use halo2_proofs::{
-    circuit::{AssignedCell, Layouter, Value, SimpleFloorPlanner},
+    circuit::{Layouter, Value, SimpleFloorPlanner},
     plonk::{Advice, Instance, Assigned, Column, ConstraintSystem,
         Constraints, Error, Expression, Selector, Circuit,
         VerifyingKey, ProvingKey, SingleVerifier, keygen_vk,
         keygen_pk, create_proof, verify_proof},
-    poly::{Rotation},
     poly::commitment::Params,
+    poly::Rotation,
     pasta::{Fp, EqAffine},
-    dev::{FailureLocation, MockProver, VerifyFailure}, 
-    transcript::{Blake2bWrite, Challenge255, Blake2bRead}   
+    dev::{MockProver, VerifyFailure},
+    transcript::{Blake2bWrite, Challenge255, Blake2bRead}
 };
 use rand_core::OsRng;
+use crate::deck::{DeckSpec, JOKER_SUITE, JOKER_RANK};
+use crate::dkg::DkgError;
+use crate::envelope::{EnvelopeError, ProofEnvelope, CARD_CIRCUIT, vk_fingerprint};
+#[cfg(test)]
+use crate::envelope::{circuit_fingerprint, to_hex};
+use crate::poseidon::{self, configure_poseidon, PoseidonChip, PoseidonConfig, PoseidonField, Word};
+use crate::qty::{QtyChip, QtyConfig, QTY_MIN};
+use crate::suite::{SuiteChip, SuiteConfig, SUITE_MIN, SUITE_MAX};
+use crate::rank::{RankChip, RankConfig, RANK_MIN, RANK_MAX};
+#[cfg(test)]
+use crate::qty::QTY_MAX;
+
+// Multiplier of the suite in the card code
+pub const CODE_SUITE_STRIDE: u64 = 16;
+
+#[derive(Debug)]
+pub enum CardError {
+    // The setup params can't be read or written
+    Params(io::Error),
+    // Generating the proving or verifying key failed
+    Keygen(Error),
+    // The circuit can't be laid out with its witnesses
+    Synthesis(Error),
+    // The witnesses don't satisfy the circuit
+    Unsatisfied(Vec<VerifyFailure>),
+    Proving(Error),
+    Verification(Error),
+    // Card values outside of their domains
+    InvalidCard { qty: u64, suite: u64, rank: u64 },
+    // A suite outside of the standard suites
+    InvalidSuite(u64),
+    // A deck spec without any deck
+    InvalidSpec { decks: u64, jokers: u64 },
+    // The cards are not as many as the deck holds
+    DeckSize { expected: usize, found: usize },
+    // A claim puts down 1 to 4 cards per deck
+    PlayedCount(usize),
+    // The cards don't fit in the slots of a hand
+    HandSize(usize),
+    // The cards don't fit in the slots of the pile
+    PileSize(usize),
+    // No leaves, or more than a tree of that depth holds
+    TreeSize { leaves: usize, depth: usize },
+    // A position past the last leaf of the tree
+    NoSuchLeaf(usize),
+    // The hands are of different players or sessions
+    OwnerMismatch,
+    // Bytes that are not a canonical field element
+    InvalidFieldElement,
+    Dkg(DkgError),
+    // The proof envelope is malformed or of another circuit, key or
+    // public inputs, or the vk is not the pinned one
+    Envelope(EnvelopeError),
+}
 
-// The length of our card inputs
-const INPUT_LENGTH: usize = 8;
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardError::Params(e) => write!(f, "invalid params: {}", e),
+            CardError::Keygen(e) => write!(f, "key generation failed: {}", e),
+            CardError::Synthesis(e) => write!(f, "circuit synthesis failed: {}", e),
+            CardError::Unsatisfied(failures) => {
+                write!(f, "circuit not satisfied")?;
+                failures.iter().try_for_each(|failure| write!(f, "; {}", failure))
+            }
+            CardError::Proving(e) => write!(f, "proof generation failed: {}", e),
+            CardError::Verification(e) => write!(f, "proof verification failed: {}", e),
+            CardError::InvalidCard { qty, suite, rank } =>
+                write!(f, "invalid card qty {} suite {} rank {}", qty, suite, rank),
+            CardError::InvalidSuite(suite) => write!(f, "invalid suite {}", suite),
+            CardError::InvalidSpec { decks, jokers } =>
+                write!(f, "invalid deck of {} decks and {} jokers", decks, jokers),
+            CardError::DeckSize { expected, found } =>
+                write!(f, "{} cards for a deck of {}", found, expected),
+            CardError::PlayedCount(count) => write!(f, "{} cards played", count),
+            CardError::HandSize(size) => write!(f, "{} cards do not fit in a hand", size),
+            CardError::PileSize(size) => write!(f, "{} cards do not fit in the pile", size),
+            CardError::TreeSize { leaves, depth } =>
+                write!(f, "{} leaves do not fit in a tree of depth {}", leaves, depth),
+            CardError::NoSuchLeaf(position) => write!(f, "no leaf at position {}", position),
+            CardError::OwnerMismatch => write!(f, "hands of different owners"),
+            CardError::InvalidFieldElement => write!(f, "invalid field element"),
+            CardError::Dkg(e) => write!(f, "key share rejected: {:?}", e),
+            CardError::Envelope(e) => write!(f, "proof envelope rejected: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CardError {}
+
+impl From<io::Error> for CardError {
+    fn from(e: io::Error) -> Self {
+        CardError::Params(e)
+    }
+}
+
+impl From<DkgError> for CardError {
+    fn from(e: DkgError) -> Self {
+        CardError::Dkg(e)
+    }
+}
+
+impl From<EnvelopeError> for CardError {
+    fn from(e: EnvelopeError) -> Self {
+        CardError::Envelope(e)
+    }
+}
 
 #[derive(Debug, Clone)]
-pub struct CardConfig {
-    qty: Column<Advice>,
-    suite: Column<Advice>,
-    rank: Column<Advice>,
-    pub_qty: Column<Instance>,
-    pub_suite: Column<Instance>,
-    pub_rank: Column<Instance>,
-    s_check: Selector,
+pub struct CardConfig<F: PoseidonField> {
+    qty: QtyConfig,
+    suite: SuiteConfig,
+    rank: RankConfig,
+    salt: Column<Advice>,
+    q_joker: Selector,
+    commitment: Column<Instance>,
+    poseidon: PoseidonConfig<F>,
+}
+
+impl<F: PoseidonField> CardConfig<F> {
+    pub(crate) fn qty(&self) -> &QtyConfig {
+        &self.qty
+    }
+
+    pub(crate) fn rank(&self) -> &RankConfig {
+        &self.rank
+    }
+
+    pub(crate) fn poseidon(&self) -> &PoseidonConfig<F> {
+        &self.poseidon
+    }
 }
 
 #[derive(Debug, Clone)]
-struct CardChip<F:PrimeField> {
-    config: CardConfig,
+pub(crate) struct CardChip<F: PoseidonField> {
+    config: CardConfig<F>,
     _marker: PhantomData<F>,
 }
 
+// The assigned card and its commitment
 #[derive(Debug, Clone)]
-struct CardConstrained<F: PrimeField>
-    (AssignedCell<Assigned<F>, F>);
+pub(crate) struct CardConstrained<F: PoseidonField> {
+    pub qty: Word<F>,
+    pub suite: Word<F>,
+    pub rank: Word<F>,
+    pub commitment: Word<F>,
+}
 
-impl<F: PrimeField> CardChip<F> {
+impl<F: PoseidonField> CardChip<F> {
 
-    fn construct(config: CardConfig) -> Self {
+    pub fn construct(config: CardConfig<F>) -> Self {
         Self { config, _marker: PhantomData}
     }
 
-    fn configure(meta: &mut ConstraintSystem<F>, qty: Column<Advice>,
-                suite: Column<Advice>, rank: Column<Advice>,
-                pub_qty: Column<Instance>, pub_suite: Column<Instance>,
-                pub_rank: Column<Instance>, s_check: Selector) -> CardConfig {
-
-        meta.enable_equality(pub_qty);
-        meta.enable_equality(pub_suite);
-        meta.enable_equality(pub_rank);
-
-        let qty_check = |value: Expression<F>| {
-            // Quantity check 1 or 2 or 3 or 4
-            (1..4).fold(value.clone(), |acc, i| {
-                acc * (Expression::Constant(F::from(i as u64)) - value.clone())
-            })
-        };
-
-        let suite_check = |value: Expression<F>| {
-            // Suite check Hearts or Spades or Diamonds or Flowers
-            (1..4).fold(value.clone(), |acc, i| {
-                acc * (Expression::Constant(F::from(i as u64)) - value.clone())
-            })
-        };
-
-        let rank_check = |value: Expression<F>| {
-            // Rank check Ace or 2 or 3 or 4 or 5 or 6 or 7 or 8 or 9
-            //                or 10 or Jack or Queen or King          
-            (1..12).fold(value.clone(), |acc, i| {
-                acc * (Expression::Constant(F::from(i as u64)) - value.clone())
-            })
-        };
-
-        // | a0  |  a1   |  a2  | selector |
-        // |-----|-------|------|----------|        
-        // | qty | suite | rank | s_check  |
-        //  
-        meta.create_gate("card check",
-            |meta| {
-                let s = meta.query_selector(s_check);
-                
-                // Private inputs
-                let qty = meta.query_advice(qty, Rotation::cur());
-                let suite = meta.query_advice(suite, Rotation::cur());
-                let rank = meta.query_advice(rank, Rotation::cur());
-
-                // Public inputs
-                let pub_qty = meta.query_instance(pub_qty, Rotation::cur());
-                let pub_suite = meta.query_instance(pub_suite, Rotation::cur());
-                let pub_rank = meta.query_instance(pub_rank, Rotation::cur());
-
-                // Card check is qty_check AND suite_check AND rank_check
-                let qty_match = qty.clone() - pub_qty; 
-                let suite_match = suite.clone() - pub_suite; 
-                let rank_match = rank.clone() - pub_rank; 
-
-                Constraints::with_selector(s,
-                        [
-                            ("qty_check", qty_check(qty)),
-                            ("suite_check", suite_check(suite)),
-                            ("rank_check", rank_check(rank)),
-                            ("qty_match", qty_match),
-                            ("suite_match", suite_match),
-                            ("rank_match", rank_match),
-                        ],
-                )
+    pub fn configure(meta: &mut ConstraintSystem<F>,
+                card: [Column<Advice>; 4], commitment: Column<Instance>,
+                poseidon: PoseidonConfig<F>) -> CardConfig<F> {
+
+        let [qty, suite, rank, salt] = card;
+
+        for column in card {
+            meta.enable_equality(column);
+        }
+        meta.enable_equality(commitment);
+
+        // | a0  |  a1   |  a2  |  a3  |
+        // |-----|-------|------|------|
+        // | qty | suite | rank | salt |
+        //
+        // Card check is qty_check AND suite_check AND rank_check,
+        // each a lookup into the table of legal values. The card
+        // itself stays private, only its commitment
+        // H(qty, suite, rank, salt) is exposed.
+        let q_qty = meta.complex_selector();
+        let q_suite = meta.complex_selector();
+        let q_rank = meta.complex_selector();
+
+        // A joker is only ever (JOKER_SUITE, JOKER_RANK). The suite is
+        // range checked, so the product of (suite - s) over the
+        // standard suites is zero unless it is the joker suite.
+        let q_joker = meta.selector();
+        meta.create_gate("joker rank", |meta| {
+            let q = meta.query_selector(q_joker);
+            let s = meta.query_advice(suite, Rotation::cur());
+            let r = meta.query_advice(rank, Rotation::cur());
+
+            let joker = (SUITE_MIN..=SUITE_MAX).fold(Expression::Constant(F::ONE),
+                |joker, suite| joker * (s.clone() - Expression::Constant(F::from(suite))));
+            Constraints::with_selector(q, Some(("joker rank",
+                joker * (r - Expression::Constant(F::from(JOKER_RANK))))))
         });
 
-        //println!("\nCreategate done:{}", meta.get_instance_query_index(qty, Rotation::cur()));
+        let qty = QtyChip::configure(meta, qty, q_qty);
+        let suite = SuiteChip::configure(meta, suite, q_suite);
+        let rank = RankChip::configure(meta, rank, q_rank);
+
         CardConfig {
             qty,
             suite,
             rank,
-            pub_qty,
-            pub_suite,
-            pub_rank,
-            s_check,
+            salt,
+            q_joker,
+            commitment,
+            poseidon,
         }
     }
 
+    // Loads the qty, suite and rank lookup tables of the spec
+    pub fn load_tables(&self, mut layouter: impl Layouter<F>, spec: &DeckSpec)
+        -> Result<(), Error> {
+
+        QtyChip::construct(self.config.qty.clone())
+            .load_table(layouter.namespace(|| "Qty Table"), spec)?;
+        SuiteChip::construct(self.config.suite.clone())
+            .load_table(layouter.namespace(|| "Suite Table"), spec)?;
+        RankChip::construct(self.config.rank.clone())
+            .load_table(layouter.namespace(|| "Rank Table"))
+    }
+
     pub fn assign(&self, mut layouter: impl Layouter<F>,
         qty: Value<Assigned<F>>, suite: Value<Assigned<F>>,
-        rank: Value<Assigned<F>>) -> 
-        Result<(CardConstrained<F>, CardConstrained<F>,
-            CardConstrained<F>), Error> {
+        rank: Value<Assigned<F>>, salt: Value<Assigned<F>>) ->
+        Result<CardConstrained<F>, Error> {
 
         let offset = 0;
 
-        layouter.assign_region( || "Card", |mut region| {
-            self.config.s_check.enable(&mut region, offset)?;
+        let cells = layouter.assign_region( || "Card", |mut region| {
+            let qty_cell = QtyChip::construct(self.config.qty.clone())
+                .assign_in_region(&mut region, offset, qty)?;
+
+            let suite_cell = SuiteChip::construct(self.config.suite.clone())
+                .assign_in_region(&mut region, offset, suite)?;
+
+            let rank_cell = RankChip::construct(self.config.rank.clone())
+                .assign_in_region(&mut region, offset, rank)?;
+
+            let salt_cell = region.
+                assign_advice(|| "salt value",
+                self.config.salt, offset, || salt)?;
 
-            let qty_cell = region
-                .assign_advice(|| "qty value",
-                self.config.qty, offset, || qty)
-                .map(CardConstrained).unwrap();
+            self.config.q_joker.enable(&mut region, offset)?;
 
-            let suite_cell = region.
-                assign_advice(|| "suite value",
-                self.config.suite, offset, || suite)
-                .map(CardConstrained).unwrap();
+            Ok([qty_cell.0, suite_cell.0, rank_cell.0, salt_cell])
+        })?;
 
-            let rank_cell: CardConstrained<F> = region.
-                assign_advice(|| "rank value",
-                self.config.rank, offset, || rank)
-                .map(CardConstrained).unwrap();
+        // Commit to the card
+        let poseidon = PoseidonChip::construct(self.config.poseidon.clone());
+        let commitment = poseidon.hash(
+            layouter.namespace(|| "Card commitment"), &cells)?;
 
-            Ok((qty_cell, suite_cell, rank_cell))
-        })
+        let [qty, suite, rank, _] = cells;
+        Ok(CardConstrained { qty, suite, rank, commitment })
+    }
+
+    // Exposes the commitment as the public input
+    pub fn expose_public(&self, mut layouter: impl Layouter<F>,
+        commitment: &CardConstrained<F>, row: usize) -> Result<(), Error> {
+
+        layouter.constrain_instance(commitment.commitment.cell(),
+            self.config.commitment, row)
     }
 }
 
 #[derive(Default)]
-pub struct CardCircuit<F: PrimeField> {
+pub struct CardCircuit<F: PoseidonField> {
+    spec: DeckSpec,
     qty: Value<Assigned<F>>,
     suite: Value<Assigned<F>>,
     rank: Value<Assigned<F>>,
+    salt: Value<Assigned<F>>,
 }
 
-impl<F: PrimeField> Circuit<F> for CardCircuit<F> {
+impl<F: PoseidonField> Circuit<F> for CardCircuit<F> {
 
-    type Config = CardConfig;
+    type Config = CardConfig<F>;
     type FloorPlanner = SimpleFloorPlanner;
 
     fn without_witnesses(&self) -> Self {
-        Self::default()
+        empty_spec_circuit(&self.spec)
     }
 
     fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
-        let qty = meta.advice_column();
-        let suite = meta.advice_column();
-        let rank = meta.advice_column();
-        let pub_qty = meta.instance_column();
-        let pub_suite = meta.instance_column();
-        let pub_rank = meta.instance_column();
-        let s_check = meta.selector();
-
-        CardChip::configure(meta, qty, suite, rank,
-                    pub_qty, pub_suite, pub_rank, s_check)
+        let commitment = meta.instance_column();
+
+        configure_card(meta, commitment)
     }
 
-    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) 
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
         -> Result<(), Error> {
 
         let chip: CardChip<F> = CardChip::construct(config);
 
-        let (qtycell, suitecell, rankcell) = chip
+        chip.load_tables(layouter.namespace(|| "Card Tables"), &self.spec)?;
+
+        let commitment = chip
             .assign(layouter.namespace(|| "Card Assign"),
-            self.qty, self.suite, self.rank).unwrap();
+            self.qty, self.suite, self.rank, self.salt)?;
 
-        Ok(())
+        chip.expose_public(layouter.namespace(|| "Card Commitment"),
+            &commitment, 0)
     }
 }
 
+// Allocates the card and hash columns and configures the card chip
+// to expose commitments in `commitment`
+pub(crate) fn configure_card<F: PoseidonField>(meta: &mut ConstraintSystem<F>,
+        commitment: Column<Instance>) -> CardConfig<F> {
+
+    let qty = meta.advice_column();
+    let suite = meta.advice_column();
+    let rank = meta.advice_column();
+    let salt = meta.advice_column();
+
+    let poseidon = configure_poseidon(meta);
+
+    CardChip::configure(meta, [qty, suite, rank, salt],
+                commitment, poseidon)
+}
+
+// Encodes a card as a single value, suite * 16 + rank. 0 is never
+// a card.
+pub fn card_code(suite: u64, rank: u64) -> u64 {
+    suite * CODE_SUITE_STRIDE + rank
+}
+
+// Commits to a card (quantity, suite, rank) under a blinding salt
+pub fn commit_card<F: PoseidonField>(qty: u64, suite: u64, rank: u64, salt: F) -> F {
+    poseidon::hash([F::from(qty), F::from(suite), F::from(rank), salt])
+}
+
 #[test]
 fn test_range_check_1() {
-    const k: u32 = 10;
+    const K: u32 = 8;
 
     // Successful case
+    let salt = Fp::from(0x5eed_u64);
     let circuit = CardCircuit::<Fp> {
-        qty: Value::known(Fp::from(1 as u64).into()),
-        suite: Value::known(Fp::from(2 as u64).into()),
-        rank: Value::known(Fp::from(3 as u64).into()),
+        spec: DeckSpec::standard(),
+        qty: Value::known(Fp::from(1_u64).into()),
+        suite: Value::known(Fp::from(2_u64).into()),
+        rank: Value::known(Fp::from(3_u64).into()),
+        salt: Value::known(salt.into()),
     };
 
-    let pubqty = Fp::from(1 as u64).into();
-    let pubsuite = Fp::from(2 as u64).into();
-    let pubrank = Fp::from(3 as u64).into();
-
-    let public_inputs = vec![vec![pubqty], vec![pubsuite], vec![pubrank]];
-    let prover = MockProver::run(k, &circuit, public_inputs).unwrap();
+    let public_inputs = vec![vec![commit_card(1, 2, 3, salt)]];
+    let prover = MockProver::run(K, &circuit, public_inputs).unwrap();
     prover.assert_satisfied();
 }
 
+#[test]
+fn test_commitment_mismatch() {
+    const K: u32 = 8;
+
+    // Commitment to a different card must not verify
+    let salt = Fp::from(0x5eed_u64);
+    let circuit = create_circuit(1, 2, 3, salt).unwrap();
+
+    for public_inputs in [create_public_inputs(1, 2, 4, salt).unwrap(),
+            create_public_inputs(1, 2, 3, salt + Fp::from(1_u64)).unwrap()] {
+        assert!(matches!(run_mock_prover(K, &circuit, &public_inputs),
+            Err(CardError::Unsatisfied(_))));
+    }
+}
+
+#[test]
+fn test_range_check_2() {
+    const K: u32 = 8;
+
+    let salt = Fp::from(0x5eed_u64);
+    let check = |qty: u64, suite: u64, rank: u64| {
+        let circuit = card_circuit(&DeckSpec::standard(), qty, suite, rank, salt);
+        let public_inputs = vec![commit_card(qty, suite, rank, salt)];
+        MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+    };
+
+    // Every legal value is accepted
+    for qty in QTY_MIN..=QTY_MAX {
+        assert_eq!(check(qty, 1, 1), Ok(()));
+    }
+    for suite in SUITE_MIN..=SUITE_MAX {
+        assert_eq!(check(1, suite, 1), Ok(()));
+    }
+    for rank in RANK_MIN..=RANK_MAX {
+        assert_eq!(check(1, 1, rank), Ok(()));
+    }
+
+    // Values outside the card domains are rejected
+    for (qty, suite, rank) in [(0, 1, 1), (5, 1, 1), (14, 1, 1),
+            (1, 0, 1), (1, 5, 1), (1, 14, 1), (1, 1, 0), (1, 1, 14)] {
+        assert!(check(qty, suite, rank).is_err(),
+            "card ({}, {}, {}) accepted", qty, suite, rank);
+        assert!(matches!(create_circuit(qty, suite, rank, salt),
+            Err(CardError::InvalidCard { .. })));
+    }
+}
+
+#[test]
+fn test_spec_ranges() {
+    const K: u32 = 8;
+
+    let salt = Fp::from(0x5eed_u64);
+    let check = |spec: &DeckSpec, qty: u64, suite: u64, rank: u64| {
+        let circuit = card_circuit(spec, qty, suite, rank, salt);
+        let public_inputs = vec![commit_card(qty, suite, rank, salt)];
+        MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+    };
+
+    // Two decks hold up to 8 cards of a kind, jokers are of their own
+    // suite
+    let spec = DeckSpec::new(2, 4).unwrap();
+    assert_eq!(check(&spec, 8, 1, 1), Ok(()));
+    assert_eq!(check(&spec, 1, JOKER_SUITE, JOKER_RANK), Ok(()));
+    assert!(check(&spec, 9, 1, 1).is_err());
+    assert!(check(&spec, 1, JOKER_SUITE + 1, 1).is_err());
+
+    // A joker has no other rank
+    for rank in RANK_MIN + 1..=RANK_MAX {
+        assert!(check(&spec, 1, JOKER_SUITE, rank).is_err(), "joker rank {} accepted", rank);
+        assert!(matches!(create_spec_circuit(&spec, 1, JOKER_SUITE, rank, salt),
+            Err(CardError::InvalidCard { .. })));
+    }
+
+    let standard = DeckSpec::standard();
+    assert!(check(&standard, 8, 1, 1).is_err());
+    assert!(check(&standard, 1, JOKER_SUITE, JOKER_RANK).is_err());
+}
+
+// Golden fingerprints of the card circuit. When they change the
+// circuit changed, and deployed verifiers reject the new proofs.
+#[test]
+fn test_fingerprints() {
+    assert_eq!(to_hex(&circuit_fingerprint::<CardCircuit<Fp>>()),
+        "30ac36ee6b34c0690161c763ce9f42b06320bbf4a2ddfd8dcfb0cd237a94c3da");
+
+    let params = generate_setup_params(8);
+    let fingerprint = card_vk_fingerprint(&params).unwrap();
+    assert_eq!(to_hex(&fingerprint), "e9fd32f7806f0b2b37847dc32ff4369ad137680a9ac2d77185337a8092538567");
+
+    // The vk depends on k and the deck
+    assert_ne!(card_vk_fingerprint(&generate_setup_params(9)).unwrap(), fingerprint);
+    let vk = keygen_vk(&params, &empty_spec_circuit(&DeckSpec::new(2, 2).unwrap())).unwrap();
+    assert!(matches!(check_vk(&vk, &fingerprint), Err(CardError::Envelope(EnvelopeError::VkMismatch { .. }))));
+    assert!(matches!(check_vk(&vk, &fingerprint[1..]),
+        Err(CardError::Envelope(EnvelopeError::Malformed))));
+    let vk = keygen_vk(&params, &empty_circuit()).unwrap();
+    assert!(check_vk(&vk, &fingerprint).is_ok());
+}
+
 // Draws the layout of the circuit
 #[cfg(not(target_family = "wasm"))]
 #[cfg(feature = "dev-graph")]
-pub fn draw_circuit<F: PrimeField>(k: u32,
+pub fn draw_circuit<F: PoseidonField>(k: u32,
             circuit: &CardCircuit<F>) {
+    use plotters::prelude::*;
 
     let base = BitMapBackend::new("layout.png",
                 (1600, 1600)).into_drawing_area();
@@ -234,37 +481,67 @@ pub fn draw_circuit<F: PrimeField>(k: u32,
 
 // Generates an empty circuit. Useful for generating
 // the proving/verfiying keys.
-pub fn empty_circuit<F: PrimeField>() -> CardCircuit<F> {
+pub fn empty_circuit<F: PoseidonField>() -> CardCircuit<F> {
+    empty_spec_circuit(&DeckSpec::standard())
+}
+
+// Generates an empty circuit for the cards of the spec
+pub fn empty_spec_circuit<F: PoseidonField>(spec: &DeckSpec) -> CardCircuit<F> {
     CardCircuit {
+        spec: *spec,
         qty: Value::unknown(),
         suite: Value::unknown(),
         rank: Value::unknown(),
+        salt: Value::unknown(),
     }
 }
 
-// Creates the circuit from the card params
-pub fn create_circuit(qty: u64, suite: u64, rank: u64) ->
-            CardCircuit<Fp> {
+// Checks the card values are in the domains of the spec
+fn check_card(spec: &DeckSpec, qty: u64, suite: u64, rank: u64) -> Result<(), CardError> {
+    if (QTY_MIN..=spec.qty_max()).contains(&qty) &&
+        (SUITE_MIN..=spec.suite_max()).contains(&suite) &&
+        (RANK_MIN..=RANK_MAX).contains(&rank) &&
+        (suite != JOKER_SUITE || rank == JOKER_RANK) {
+        Ok(())
+    } else {
+        Err(CardError::InvalidCard { qty, suite, rank })
+    }
+}
 
-    let circuit = CardCircuit::<Fp> {
-        qty: Value::known(Fp::from(qty as u64).into()),
-        suite: Value::known(Fp::from(suite as u64).into()),
-        rank: Value::known(Fp::from(rank as u64).into()),
-    };
+// Creates the circuit from the card params and the blinding salt
+pub fn create_circuit(qty: u64, suite: u64, rank: u64, salt: Fp) ->
+            Result<CardCircuit<Fp>, CardError> {
 
-    circuit
+    create_spec_circuit(&DeckSpec::standard(), qty, suite, rank, salt)
 }
 
-// Formats the public inputs (quantity, suite, rank)
-pub fn create_public_inputs(qty: u64, suite: u64, rank: u64) -> 
-        (Vec<Fp>, Vec<Fp>, Vec<Fp>) {
-    let pubqty = Fp::from(qty as u64).into();
-    let pubsuite = Fp::from(suite as u64).into();
-    let pubrank = Fp::from(rank as u64).into();
+// Creates the circuit of a card from the cards of the spec
+pub fn create_spec_circuit(spec: &DeckSpec, qty: u64, suite: u64, rank: u64,
+            salt: Fp) -> Result<CardCircuit<Fp>, CardError> {
 
-    let public_inputs = (vec!(pubqty), vec!(pubsuite), vec!(pubrank));
+    check_card(spec, qty, suite, rank)?;
+    Ok(card_circuit(spec, qty, suite, rank, salt))
+}
 
-    public_inputs
+// The circuit of any card values, even out of their domains
+fn card_circuit(spec: &DeckSpec, qty: u64, suite: u64, rank: u64, salt: Fp)
+            -> CardCircuit<Fp> {
+
+    CardCircuit::<Fp> {
+        spec: *spec,
+        qty: Value::known(Fp::from(qty).into()),
+        suite: Value::known(Fp::from(suite).into()),
+        rank: Value::known(Fp::from(rank).into()),
+        salt: Value::known(salt.into()),
+    }
+}
+
+// Formats the public inputs, i.e the card commitment
+pub fn create_public_inputs(qty: u64, suite: u64, rank: u64, salt: Fp) ->
+        Result<Vec<Fp>, CardError> {
+
+    check_card(&DeckSpec::standard(), qty, suite, rank)?;
+    Ok(vec![commit_card(qty, suite, rank, salt)])
 }
 
 // Generates setup params using k, which is the number of
@@ -275,59 +552,73 @@ pub fn generate_setup_params(k: u32) -> Params<EqAffine> {
 
 // Generates the proving and verifying keys. We can pass an
 // empty circuit to it
-pub fn generate_keys<F: PrimeField>(params: &Params<EqAffine>,
-        circuit: &CardCircuit<Fp>) -> 
-        (ProvingKey<EqAffine>, VerifyingKey<EqAffine>) {
+pub fn generate_keys<F: PoseidonField>(params: &Params<EqAffine>,
+        circuit: &CardCircuit<Fp>) ->
+        Result<(ProvingKey<EqAffine>, VerifyingKey<EqAffine>), CardError> {
 
     let vk = keygen_vk(params, circuit)
-                .expect("Failed to generate vk");
+                .map_err(CardError::Keygen)?;
     let pk = keygen_pk(params, vk.clone(), circuit)
-                .expect("Failed to generate pk");
+                .map_err(CardError::Keygen)?;
 
-    (pk, vk)
+    Ok((pk, vk))
 }
 
-pub fn run_mock_prover(k: u32, circuit: &CardCircuit<Fp>,
-        public_inputs: (&Vec<Fp>, &Vec<Fp>, &Vec<Fp>)) {
+// Fingerprint of the card circuit vk under the params
+pub fn card_vk_fingerprint(params: &Params<EqAffine>) -> Result<[u8; 32], CardError> {
+    let vk = keygen_vk(params, &empty_circuit()).map_err(CardError::Keygen)?;
 
-    let a = public_inputs.0.clone();
-    let b = public_inputs.1.clone();
-    let c = public_inputs.2.clone();
+    Ok(vk_fingerprint(&vk))
+}
 
-    let prover = MockProver::run(k, circuit, vec![a, b, c])
-        .expect("Failed to run mock prover..");
+// Checks the vk is the one pinned by its fingerprint
+pub fn check_vk(vk: &VerifyingKey<EqAffine>, pinned: &[u8]) -> Result<(), CardError> {
+    let expected: [u8; 32] = pinned.try_into().map_err(|_| EnvelopeError::Malformed)?;
+    let found = vk_fingerprint(vk);
+    if found != expected {
+        return Err(EnvelopeError::VkMismatch { expected, found }.into());
+    }
 
-    prover.assert_satisfied();
+    Ok(())
+}
+
+pub fn run_mock_prover(k: u32, circuit: &CardCircuit<Fp>,
+        public_inputs: &[Fp]) -> Result<(), CardError> {
+
+    let prover = MockProver::run(k, circuit, vec![public_inputs.to_vec()])
+        .map_err(CardError::Synthesis)?;
+
+    prover.verify().map_err(CardError::Unsatisfied)
 }
 
-// Generates the proof
+// Generates the proof, in an envelope recording its vk and public
+// inputs
 pub fn generate_proof( params: &Params<EqAffine>,
         pk: &ProvingKey<EqAffine>, circuit: CardCircuit<Fp>,
-        public_inputs: (&Vec<Fp>, &Vec<Fp>, &Vec<Fp>)) -> Vec<u8> {
-
-    println!("Generating proof..");
+        public_inputs: &[Fp]) -> Result<ProofEnvelope, CardError> {
 
     let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
-    
-    create_proof( params, pk, &[circuit], &[&[&public_inputs.0, 
-        &public_inputs.1, &public_inputs.2]], OsRng, &mut transcript
+
+    create_proof( params, pk, &[circuit], &[&[public_inputs]],
+        OsRng, &mut transcript
     )
-    .expect("Failed to create proof");
-    transcript.finalize()
+    .map_err(CardError::Proving)?;
+    Ok(ProofEnvelope::new(CARD_CIRCUIT, pk.get_vk(), public_inputs,
+        transcript.finalize()))
 }
 
-// Verifies the proof
+// Verifies the proof, after checking the envelope is of the card
+// circuit under vk and of the public inputs
 pub fn verify(params: &Params<EqAffine>, vk: &VerifyingKey<EqAffine>,
-            public_inputs: (&Vec<Fp>, &Vec<Fp>, &Vec<Fp>), proof: Vec<u8>) -> 
-            Result<(), Error> {
+            public_inputs: &[Fp], envelope: &ProofEnvelope) ->
+            Result<(), CardError> {
 
-    println!("Verifying proof..");
+    envelope.check(CARD_CIRCUIT, vk, public_inputs)?;
 
-    let strategy = SingleVerifier::new(&params);
-    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&proof[..]);
+    let strategy = SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(&envelope.proof[..]);
 
     verify_proof(
-        params, vk, strategy, &[&[&public_inputs.0, &public_inputs.1,
-        &public_inputs.2]], &mut transcript
-    )
-}
\ No newline at end of file
+        params, vk, strategy, &[&[public_inputs]], &mut transcript
+    ).map_err(CardError::Verification)
+}
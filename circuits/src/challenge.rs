@@ -0,0 +1,368 @@
+use std::marker::PhantomData;
+use halo2_proofs::{
+    circuit::{Layouter, Value, SimpleFloorPlanner},
+    plonk::{Advice, Instance, Assigned, Column, ConstraintSystem,
+        Constraints, Error, Expression, Selector, Circuit, VirtualCells},
+    poly::Rotation,
+    pasta::Fp,
+};
+#[cfg(test)]
+use halo2_proofs::dev::MockProver;
+
+use crate::poseidon::PoseidonField;
+use crate::card::{configure_card, CardError};
+use crate::claim::{PlayedChip, PlayedConfig, PlayedCard, played_cards,
+    create_claim_public_inputs, played_slots, claim_rows};
+use crate::deck::DeckSpec;
+#[cfg(test)]
+use crate::deck::{card_commitments, JOKER_SUITE, JOKER_RANK};
+
+// Row of the public bluff bit, after the claim rows
+pub fn bluff_row(spec: &DeckSpec) -> usize {
+    claim_rows(spec)
+}
+
+#[derive(Debug, Clone)]
+pub struct ChallengeConfig<F: PoseidonField> {
+    played: PlayedConfig<F>,
+    active: Column<Advice>,
+    rank: Column<Advice>,
+    claimed: Column<Advice>,
+    joker: Column<Advice>,
+    inverse: Column<Advice>,
+    mismatch: Column<Advice>,
+    honest: Column<Advice>,
+    bluff: Column<Advice>,
+    instance: Column<Instance>,
+    s_slot: Selector,
+    s_first: Selector,
+    s_next: Selector,
+    s_last: Selector,
+}
+
+#[derive(Debug, Clone)]
+struct ChallengeChip<F: PoseidonField> {
+    config: ChallengeConfig<F>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> ChallengeChip<F> {
+
+    fn construct(config: ChallengeConfig<F>) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>, played: PlayedConfig<F>,
+                instance: Column<Instance>) -> ChallengeConfig<F> {
+
+        let active = meta.advice_column();
+        let rank = meta.advice_column();
+        let claimed = meta.advice_column();
+        let joker = meta.advice_column();
+        let inverse = meta.advice_column();
+        let mismatch = meta.advice_column();
+        let honest = meta.advice_column();
+        let bluff = meta.advice_column();
+        let s_slot = meta.selector();
+        let s_first = meta.selector();
+        let s_next = meta.selector();
+        let s_last = meta.selector();
+
+        for column in [active, rank, claimed, joker, bluff] {
+            meta.enable_equality(column);
+        }
+
+        let one = Expression::Constant(F::ONE);
+
+        // | active | rank | claimed | joker | inverse | mismatch | honest | bluff |
+        // |--------|------|---------|-------|---------|----------|--------|-------|
+        // |   a0   |  r0  |    c    |  j0   |  1/d0   |    m0    |   p0   |       |
+        // |   a1   |  r1  |    c    |  j1   |  1/d1   |    m1    |   p1   |   b   |
+        //
+        // m = 1 iff r != c, p is the running product of
+        // (1 - a * m * (1 - j)) and b = 1 - p on the last slot. A
+        // joker never mismatches, jokers are wild.
+        meta.create_gate("challenge mismatch", |meta| {
+            let s = meta.query_selector(s_slot);
+            let r = meta.query_advice(rank, Rotation::cur());
+            let c = meta.query_advice(claimed, Rotation::cur());
+            let inv = meta.query_advice(inverse, Rotation::cur());
+            let m = meta.query_advice(mismatch, Rotation::cur());
+            let d = r - c;
+
+            Constraints::with_selector(s, [
+                ("mismatch", m.clone() - d.clone() * inv),
+                ("match", d * (one.clone() - m)),
+            ])
+        });
+
+        let honest_slot = |meta: &mut VirtualCells<F>| {
+            let a = meta.query_advice(active, Rotation::cur());
+            let m = meta.query_advice(mismatch, Rotation::cur());
+            let j = meta.query_advice(joker, Rotation::cur());
+            one.clone() - a * m * (one.clone() - j)
+        };
+
+        meta.create_gate("challenge first slot", |meta| {
+            let s = meta.query_selector(s_first);
+            let p = meta.query_advice(honest, Rotation::cur());
+
+            Constraints::with_selector(s, Some(("honest", p - honest_slot(meta))))
+        });
+
+        meta.create_gate("challenge next slot", |meta| {
+            let s = meta.query_selector(s_next);
+            let p_prev = meta.query_advice(honest, Rotation::prev());
+            let p = meta.query_advice(honest, Rotation::cur());
+
+            Constraints::with_selector(s,
+                Some(("honest", p - p_prev * honest_slot(meta))))
+        });
+
+        meta.create_gate("challenge result", |meta| {
+            let s = meta.query_selector(s_last);
+            let p = meta.query_advice(honest, Rotation::cur());
+            let b = meta.query_advice(bluff, Rotation::cur());
+
+            Constraints::with_selector(s, Some(("bluff", b - (one.clone() - p))))
+        });
+
+        ChallengeConfig {
+            played,
+            active,
+            rank,
+            claimed,
+            joker,
+            inverse,
+            mismatch,
+            honest,
+            bluff,
+            instance,
+            s_slot,
+            s_first,
+            s_next,
+            s_last,
+        }
+    }
+
+    pub fn assign(&self, mut layouter: impl Layouter<F>, spec: &DeckSpec,
+        claimed_rank: Value<Assigned<F>>, count: Value<Assigned<F>>,
+        cards: &[PlayedCard<F>]) -> Result<(), Error> {
+
+        let played = PlayedChip::construct(self.config.played.clone());
+        played.load_tables(layouter.namespace(|| "Card Tables"), spec)?;
+
+        let slots = played.assign(layouter.namespace(|| "Played"),
+            claimed_rank, count, cards)?;
+
+        let bluff = layouter.assign_region(|| "Challenge", |mut region| {
+            let mut honest = Value::known(Assigned::from(F::ONE));
+            let mut bluff = None;
+            let last = slots.active.len() - 1;
+
+            for (offset, ((active, rank), joker)) in slots.active.iter()
+                .zip(&slots.rank).zip(&slots.joker).enumerate() {
+                self.config.s_slot.enable(&mut region, offset)?;
+                if offset == 0 {
+                    self.config.s_first.enable(&mut region, offset)?;
+                } else {
+                    self.config.s_next.enable(&mut region, offset)?;
+                }
+
+                let a = active.copy_advice(|| "active", &mut region,
+                    self.config.active, offset)?;
+                let r = rank.copy_advice(|| "rank", &mut region,
+                    self.config.rank, offset)?;
+                let c = slots.claimed.copy_advice(|| "claimed", &mut region,
+                    self.config.claimed, offset)?;
+                let j = joker.copy_advice(|| "joker", &mut region,
+                    self.config.joker, offset)?;
+
+                let d = r.value().copied() - c.value().copied();
+                region.assign_advice(|| "inverse", self.config.inverse,
+                    offset, || d.map(|d| d.invert()))?;
+
+                let m = d.map(|d| if d.is_zero_vartime() {
+                    Assigned::from(F::ZERO)
+                } else {
+                    Assigned::from(F::ONE)
+                });
+                region.assign_advice(|| "mismatch", self.config.mismatch,
+                    offset, || m)?;
+
+                let one = Value::known(Assigned::from(F::ONE));
+                honest = honest * (one - a.value().copied() * m *
+                    (one - j.value().copied()));
+                region.assign_advice(|| "honest", self.config.honest,
+                    offset, || honest)?;
+
+                if offset == last {
+                    self.config.s_last.enable(&mut region, offset)?;
+                    bluff = Some(region.assign_advice(|| "bluff",
+                        self.config.bluff, offset,
+                        || Value::known(Assigned::from(F::ONE)) - honest)?);
+                }
+            }
+
+            bluff.ok_or(Error::Synthesis)
+        })?;
+
+        layouter.constrain_instance(bluff.cell(), self.config.instance, bluff_row(spec))
+    }
+}
+
+// Settles a "Bluff!" call: given the committed played cards, proves
+// whether at least one of them does not have the claimed rank. A
+// joker stands for any rank. Only that bit is revealed.
+pub struct ChallengeCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    claimed_rank: Value<Assigned<F>>,
+    count: Value<Assigned<F>>,
+    cards: Vec<PlayedCard<F>>,
+}
+
+impl<F: PoseidonField> ChallengeCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            claimed_rank: Value::unknown(),
+            count: Value::unknown(),
+            cards: vec![PlayedCard::default(); played_slots(&spec)],
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for ChallengeCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for ChallengeCircuit<F> {
+
+    type Config = ChallengeConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let card = configure_card(meta, instance);
+        let played = PlayedChip::configure(meta, card, instance);
+
+        ChallengeChip::configure(meta, played, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let chip: ChallengeChip<F> = ChallengeChip::construct(config);
+
+        chip.assign(layouter.namespace(|| "Challenge Assign"), &self.spec,
+            self.claimed_rank, self.count, &self.cards)
+    }
+}
+
+// Creates the challenge circuit of the spec from the played cards
+// (suite, rank, salt)
+pub fn create_challenge_circuit(spec: &DeckSpec, claimed_rank: u64,
+    cards: &[(u64, u64, Fp)]) -> Result<ChallengeCircuit<Fp>, CardError> {
+
+    Ok(ChallengeCircuit {
+        spec: *spec,
+        claimed_rank: Value::known(Fp::from(claimed_rank).into()),
+        count: Value::known(Fp::from(cards.len() as u64).into()),
+        cards: played_cards(spec, claimed_rank, cards)?,
+    })
+}
+
+// Formats the public inputs, the claim followed by the bluff bit
+pub fn create_challenge_public_inputs(spec: &DeckSpec, claimed_rank: u64,
+    commitments: &[Fp], bluff: bool) -> Result<Vec<Fp>, CardError> {
+
+    let mut public_inputs = create_claim_public_inputs(spec, claimed_rank, commitments)?;
+    public_inputs.push(Fp::from(bluff as u64));
+
+    Ok(public_inputs)
+}
+
+#[cfg(test)]
+fn run_challenge(claimed_rank: u64, cards: &[(u64, u64, Fp)], bluff: bool)
+    -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    run_spec_challenge(&DeckSpec::standard(), claimed_rank, cards, bluff)
+}
+
+#[cfg(test)]
+fn run_spec_challenge(spec: &DeckSpec, claimed_rank: u64, cards: &[(u64, u64, Fp)],
+    bluff: bool) -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 10;
+
+    let circuit = create_challenge_circuit(spec, claimed_rank, cards).unwrap();
+    let public_inputs = create_challenge_public_inputs(spec, claimed_rank,
+        &card_commitments(cards), bluff).unwrap();
+    MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+}
+
+#[test]
+fn test_challenge_bluff() {
+    // The last card is a Queen, not an Ace
+    let cards = [(1, 1, Fp::from(21)), (2, 1, Fp::from(22)), (3, 12, Fp::from(23))];
+
+    assert_eq!(run_challenge(1, &cards, true), Ok(()));
+    assert!(run_challenge(1, &cards, false).is_err());
+}
+
+#[test]
+fn test_challenge_honest() {
+    let cards = [(1, 1, Fp::from(21)), (2, 1, Fp::from(22)), (3, 1, Fp::from(23))];
+
+    for n in 1..=cards.len() {
+        assert_eq!(run_challenge(1, &cards[..n], false), Ok(()));
+        assert!(run_challenge(1, &cards[..n], true).is_err());
+    }
+}
+
+#[test]
+fn test_challenge_ignores_unplayed_slots() {
+    // Padding slots hold a card of the claimed rank, a mismatch
+    // can only come from the played cards
+    let cards = [(4, 13, Fp::from(21))];
+
+    assert_eq!(run_challenge(13, &cards, false), Ok(()));
+    assert_eq!(run_challenge(12, &cards, true), Ok(()));
+}
+
+#[test]
+fn test_challenge_two_decks() {
+    // Eight cards of two decks, the last one a 3 instead of a 5
+    let spec = DeckSpec::new(2, 0).unwrap();
+    let mut cards: Vec<(u64, u64, Fp)> = (0..8)
+        .map(|i| (i % 4 + 1, 5, Fp::from(21 + i)))
+        .collect();
+
+    assert_eq!(run_spec_challenge(&spec, 5, &cards, false), Ok(()));
+    cards[7].1 = 3;
+    assert_eq!(run_spec_challenge(&spec, 5, &cards, true), Ok(()));
+    assert!(run_spec_challenge(&spec, 5, &cards, false).is_err());
+}
+
+#[test]
+fn test_challenge_wild_jokers() {
+    let spec = DeckSpec::new(1, 2).unwrap();
+    let run = |cards: &[(u64, u64, Fp)], bluff: bool| run_spec_challenge(&spec, 9, cards, bluff);
+
+    // Jokers stand for the claimed rank
+    let cards = [(2, 9, Fp::from(21)), (JOKER_SUITE, JOKER_RANK, Fp::from(22)),
+                (JOKER_SUITE, JOKER_RANK, Fp::from(23))];
+    assert_eq!(run(&cards, false), Ok(()));
+    assert!(run(&cards, true).is_err());
+
+    // An Ace is not a joker
+    let cards = [(2, 9, Fp::from(21)), (1, JOKER_RANK, Fp::from(22))];
+    assert_eq!(run(&cards, true), Ok(()));
+}
@@ -0,0 +1,583 @@
+use std::marker::PhantomData;
+use ff::Field;
+use halo2_proofs::{
+    circuit::{Layouter, Value, SimpleFloorPlanner},
+    plonk::{Advice, Instance, Assigned, Column, ConstraintSystem,
+        Constraints, Error, Expression, Selector, Circuit},
+    poly::Rotation,
+    pasta::Fp,
+};
+#[cfg(test)]
+use halo2_proofs::dev::MockProver;
+
+use crate::card::{CardChip, CardConfig, CardError, configure_card};
+use crate::deck::{DeckSpec, JOKER_SUITE};
+#[cfg(test)]
+use crate::deck::{card_commitments, JOKER_RANK};
+use crate::poseidon::{PoseidonField, Word};
+use crate::qty::QtyChip;
+use crate::rank::RankChip;
+use crate::suite::{SUITE_MIN, SUITE_MAX};
+
+// Rows of the public input column
+pub const CLAIMED_RANK_ROW: usize = 0;
+pub const PLAYED_COUNT_ROW: usize = 1;
+pub const COMMITMENTS_ROW: usize = 2;
+
+// A claim has a slot per card of a rank in the deck, i.e 4 per deck
+pub fn played_slots(spec: &DeckSpec) -> usize {
+    spec.qty_max() as usize
+}
+
+// Rows taken by the claim, the commitment slots being the last ones
+pub fn claim_rows(spec: &DeckSpec) -> usize {
+    COMMITMENTS_ROW + played_slots(spec)
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayedConfig<F: PoseidonField> {
+    card: CardConfig<F>,
+    active: Column<Advice>,
+    commitment: Column<Advice>,
+    exposed: Column<Advice>,
+    count: Column<Advice>,
+    suite: Column<Advice>,
+    joker: Column<Advice>,
+    instance: Column<Instance>,
+    s_slot: Selector,
+    s_first: Selector,
+    s_next: Selector,
+}
+
+// The played cards of a claim: the claimed rank, the number of
+// cards and, per slot, whether it is played, its card, whether it is
+// a joker and its exposed commitment
+#[derive(Debug, Clone)]
+pub(crate) struct PlayedSlots<F: PoseidonField> {
+    pub claimed: Word<F>,
+    pub active: Vec<Word<F>>,
+    pub suite: Vec<Word<F>>,
+    pub rank: Vec<Word<F>>,
+    pub joker: Vec<Word<F>>,
+    pub exposed: Vec<Word<F>>,
+}
+
+// Commits the played cards and exposes the claim. Shared by the
+// claim and the challenge circuits.
+#[derive(Debug, Clone)]
+pub(crate) struct PlayedChip<F: PoseidonField> {
+    config: PlayedConfig<F>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> PlayedChip<F> {
+
+    pub fn construct(config: PlayedConfig<F>) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>, card: CardConfig<F>,
+                instance: Column<Instance>) -> PlayedConfig<F> {
+
+        let active = meta.advice_column();
+        let commitment = meta.advice_column();
+        let exposed = meta.advice_column();
+        let count = meta.advice_column();
+        let suite = meta.advice_column();
+        let joker = meta.advice_column();
+        let s_slot = meta.selector();
+        let s_first = meta.selector();
+        let s_next = meta.selector();
+
+        for column in [active, commitment, exposed, count, suite, joker] {
+            meta.enable_equality(column);
+        }
+        meta.enable_equality(instance);
+
+        let one = Expression::Constant(F::ONE);
+
+        // The product of (suite - s) over the standard suites, at the
+        // joker suite
+        let joker_product = (SUITE_MIN..=SUITE_MAX)
+            .fold(F::ONE, |product, s| product * F::from(JOKER_SUITE - s));
+
+        // | active | commitment | exposed | count | suite | joker | selector        |
+        // |--------|------------|---------|-------|-------|-------|-----------------|
+        // |   a0   |     h0     |   e0    |  a0   |  s0   |  j0   | s_slot, s_first |
+        // |   a1   |     h1     |   e1    | +a1   |  s1   |  j1   | s_slot, s_next  |
+        //
+        // An active slot holds a played card and exposes its
+        // commitment, an inactive slot exposes 0. The suite is range
+        // checked, so the product of (s - suite) over the standard
+        // suites is 0 unless s is the joker suite: j = 1 iff the card
+        // is a joker.
+        meta.create_gate("played slot", |meta| {
+            let s = meta.query_selector(s_slot);
+            let a = meta.query_advice(active, Rotation::cur());
+            let h = meta.query_advice(commitment, Rotation::cur());
+            let e = meta.query_advice(exposed, Rotation::cur());
+            let suite = meta.query_advice(suite, Rotation::cur());
+            let j = meta.query_advice(joker, Rotation::cur());
+
+            let product = (SUITE_MIN..=SUITE_MAX).fold(one.clone(),
+                |product, s| product * (suite.clone() - Expression::Constant(F::from(s))));
+
+            Constraints::with_selector(s, [
+                ("active is boolean", a.clone() * (one.clone() - a.clone())),
+                ("exposed commitment", e - a * h),
+                ("joker", j * Expression::Constant(joker_product) - product),
+            ])
+        });
+
+        // Active slots come first and the running count adds them up
+        meta.create_gate("played next slot", |meta| {
+            let s = meta.query_selector(s_next);
+            let a_prev = meta.query_advice(active, Rotation::prev());
+            let a = meta.query_advice(active, Rotation::cur());
+            let n_prev = meta.query_advice(count, Rotation::prev());
+            let n = meta.query_advice(count, Rotation::cur());
+
+            Constraints::with_selector(s, [
+                ("active prefix", a.clone() * (one.clone() - a_prev)),
+                ("count", n - n_prev - a),
+            ])
+        });
+
+        // The first slot starts the count
+        meta.create_gate("played first slot", |meta| {
+            let s = meta.query_selector(s_first);
+            let a = meta.query_advice(active, Rotation::cur());
+            let n = meta.query_advice(count, Rotation::cur());
+
+            Constraints::with_selector(s, Some(("count", n - a)))
+        });
+
+        PlayedConfig {
+            card,
+            active,
+            commitment,
+            exposed,
+            count,
+            suite,
+            joker,
+            instance,
+            s_slot,
+            s_first,
+            s_next,
+        }
+    }
+
+    pub fn load_tables(&self, layouter: impl Layouter<F>, spec: &DeckSpec)
+        -> Result<(), Error> {
+
+        CardChip::construct(self.config.card.clone()).load_tables(layouter, spec)
+    }
+
+    pub fn assign(&self, mut layouter: impl Layouter<F>,
+        claimed_rank: Value<Assigned<F>>, count: Value<Assigned<F>>,
+        cards: &[PlayedCard<F>]) -> Result<PlayedSlots<F>, Error> {
+
+        let card_chip = CardChip::construct(self.config.card.clone());
+
+        // Played cards are single cards, i.e quantity 1
+        let mut assigned = Vec::with_capacity(cards.len());
+        for (i, card) in cards.iter().enumerate() {
+            let cell = card_chip.assign(
+                layouter.namespace(|| format!("Played card {}", i)),
+                Value::known(F::ONE.into()), card.suite, card.rank, card.salt)?;
+            layouter.assign_region(|| "single card", |mut region| {
+                region.constrain_constant(cell.qty.cell(), F::ONE)
+            })?;
+            assigned.push(cell);
+        }
+
+        // Range check the claim itself
+        let claimed_cell = layouter.assign_region(|| "Claim", |mut region| {
+            RankChip::construct(self.config.card.rank().clone())
+                .assign_in_region(&mut region, 0, claimed_rank)
+        })?;
+        let count_cell = layouter.assign_region(|| "Played count", |mut region| {
+            QtyChip::construct(self.config.card.qty().clone())
+                .assign_in_region(&mut region, 0, count)
+        })?;
+
+        let joker_suite = Assigned::from(F::from(JOKER_SUITE));
+
+        let (active, joker, exposed, total) = layouter.assign_region(|| "Played slots",
+            |mut region| {
+            let mut active: Vec<Word<F>> = Vec::with_capacity(cards.len());
+            let mut joker: Vec<Word<F>> = Vec::with_capacity(cards.len());
+            let mut exposed: Vec<Word<F>> = Vec::with_capacity(cards.len());
+            let mut total: Option<Word<F>> = None;
+            let mut running = Value::known(Assigned::from(F::ZERO));
+
+            for (offset, (card, cell)) in cards.iter().zip(&assigned).enumerate() {
+                self.config.s_slot.enable(&mut region, offset)?;
+                if offset == 0 {
+                    self.config.s_first.enable(&mut region, offset)?;
+                } else {
+                    self.config.s_next.enable(&mut region, offset)?;
+                }
+
+                active.push(region.assign_advice(|| "active", self.config.active,
+                    offset, || card.active)?);
+                cell.commitment.copy_advice(|| "commitment", &mut region,
+                    self.config.commitment, offset)?;
+
+                let s = cell.suite.copy_advice(|| "suite", &mut region,
+                    self.config.suite, offset)?;
+                let j = s.value().map(|s| if (*s - joker_suite).is_zero_vartime() {
+                    Assigned::from(F::ONE)
+                } else {
+                    Assigned::from(F::ZERO)
+                });
+                joker.push(region.assign_advice(|| "joker", self.config.joker,
+                    offset, || j)?);
+
+                let e = card.active * cell.commitment.value().copied();
+                exposed.push(region.assign_advice(|| "exposed",
+                    self.config.exposed, offset, || e)?);
+
+                running = running + card.active;
+                total = Some(region.assign_advice(|| "count",
+                    self.config.count, offset, || running)?);
+            }
+
+            Ok((active, joker, exposed, total.ok_or(Error::Synthesis)?))
+        })?;
+
+        layouter.assign_region(|| "Count matches", |mut region| {
+            region.constrain_equal(total.cell(), count_cell.0.cell())
+        })?;
+
+        layouter.constrain_instance(claimed_cell.0.cell(),
+            self.config.instance, CLAIMED_RANK_ROW)?;
+        layouter.constrain_instance(count_cell.0.cell(),
+            self.config.instance, PLAYED_COUNT_ROW)?;
+        for (i, cell) in exposed.iter().enumerate() {
+            layouter.constrain_instance(cell.cell(),
+                self.config.instance, COMMITMENTS_ROW + i)?;
+        }
+
+        let (suite, rank) = assigned.into_iter()
+            .map(|cell| (cell.suite, cell.rank)).unzip();
+
+        Ok(PlayedSlots {
+            claimed: claimed_cell.0,
+            active,
+            suite,
+            rank,
+            joker,
+            exposed,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClaimConfig<F: PoseidonField> {
+    played: PlayedConfig<F>,
+    active: Column<Advice>,
+    rank: Column<Advice>,
+    claimed: Column<Advice>,
+    joker: Column<Advice>,
+    s_match: Selector,
+}
+
+#[derive(Debug, Clone)]
+struct ClaimChip<F: PoseidonField> {
+    config: ClaimConfig<F>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> ClaimChip<F> {
+
+    fn construct(config: ClaimConfig<F>) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>,
+                played: PlayedConfig<F>) -> ClaimConfig<F> {
+
+        let active = meta.advice_column();
+        let rank = meta.advice_column();
+        let claimed = meta.advice_column();
+        let joker = meta.advice_column();
+        let s_match = meta.selector();
+
+        for column in [active, rank, claimed, joker] {
+            meta.enable_equality(column);
+        }
+
+        let one = Expression::Constant(F::ONE);
+
+        // | active | rank | claimed | joker | selector |
+        // |--------|------|---------|-------|----------|
+        // |   a    |  r   |    c    |   j   | s_match  |
+        //
+        // Every played card has the claimed rank or is a joker, jokers
+        // are wild
+        meta.create_gate("claim match", |meta| {
+            let s = meta.query_selector(s_match);
+            let a = meta.query_advice(active, Rotation::cur());
+            let r = meta.query_advice(rank, Rotation::cur());
+            let c = meta.query_advice(claimed, Rotation::cur());
+            let j = meta.query_advice(joker, Rotation::cur());
+
+            Constraints::with_selector(s, Some(("rank matches claim",
+                a * (r - c) * (one.clone() - j))))
+        });
+
+        ClaimConfig {
+            played,
+            active,
+            rank,
+            claimed,
+            joker,
+            s_match,
+        }
+    }
+
+    pub fn assign(&self, mut layouter: impl Layouter<F>, spec: &DeckSpec,
+        claimed_rank: Value<Assigned<F>>, count: Value<Assigned<F>>,
+        cards: &[PlayedCard<F>]) -> Result<(), Error> {
+
+        let played = PlayedChip::construct(self.config.played.clone());
+        played.load_tables(layouter.namespace(|| "Card Tables"), spec)?;
+
+        let slots = played.assign(layouter.namespace(|| "Played"),
+            claimed_rank, count, cards)?;
+
+        layouter.assign_region(|| "Claim match", |mut region| {
+            for (offset, ((active, rank), joker)) in slots.active.iter()
+                .zip(&slots.rank).zip(&slots.joker).enumerate() {
+                self.config.s_match.enable(&mut region, offset)?;
+                active.copy_advice(|| "active", &mut region,
+                    self.config.active, offset)?;
+                rank.copy_advice(|| "rank", &mut region,
+                    self.config.rank, offset)?;
+                joker.copy_advice(|| "joker", &mut region,
+                    self.config.joker, offset)?;
+                slots.claimed.copy_advice(|| "claimed", &mut region,
+                    self.config.claimed, offset)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// A face down card in the claim. Unused slots are inactive and
+// hold any legal card.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayedCard<F: PoseidonField> {
+    pub(crate) active: Value<Assigned<F>>,
+    pub(crate) suite: Value<Assigned<F>>,
+    pub(crate) rank: Value<Assigned<F>>,
+    pub(crate) salt: Value<Assigned<F>>,
+}
+
+impl<F: PoseidonField> Default for PlayedCard<F> {
+    fn default() -> Self {
+        Self {
+            active: Value::unknown(),
+            suite: Value::unknown(),
+            rank: Value::unknown(),
+            salt: Value::unknown(),
+        }
+    }
+}
+
+// Proves that the `count` committed played cards all have the
+// claimed rank, jokers standing for any rank
+pub struct ClaimCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    claimed_rank: Value<Assigned<F>>,
+    count: Value<Assigned<F>>,
+    cards: Vec<PlayedCard<F>>,
+}
+
+impl<F: PoseidonField> ClaimCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            claimed_rank: Value::unknown(),
+            count: Value::unknown(),
+            cards: vec![PlayedCard::default(); played_slots(&spec)],
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for ClaimCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for ClaimCircuit<F> {
+
+    type Config = ClaimConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let card = configure_card(meta, instance);
+        let played = PlayedChip::configure(meta, card, instance);
+
+        ClaimChip::configure(meta, played)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let chip: ClaimChip<F> = ClaimChip::construct(config);
+
+        chip.assign(layouter.namespace(|| "Claim Assign"), &self.spec,
+            self.claimed_rank, self.count, &self.cards)
+    }
+}
+
+// Checks a claim of the spec puts down 1 to `played_slots` cards
+fn check_played_count(spec: &DeckSpec, count: usize) -> Result<(), CardError> {
+    if count == 0 || count > played_slots(spec) {
+        return Err(CardError::PlayedCount(count));
+    }
+    Ok(())
+}
+
+// Fills the played card slots of the spec from (suite, rank, salt)
+pub(crate) fn played_cards(spec: &DeckSpec, claimed_rank: u64, cards: &[(u64, u64, Fp)])
+    -> Result<Vec<PlayedCard<Fp>>, CardError> {
+
+    check_played_count(spec, cards.len())?;
+
+    Ok((0..played_slots(spec)).map(|i| {
+        // Pad with an inactive card of the claimed rank
+        let (active, (suite, rank, salt)) = match cards.get(i) {
+            Some(card) => (1, *card),
+            None => (0, (1, claimed_rank, Fp::ZERO)),
+        };
+
+        PlayedCard {
+            active: Value::known(Fp::from(active).into()),
+            suite: Value::known(Fp::from(suite).into()),
+            rank: Value::known(Fp::from(rank).into()),
+            salt: Value::known(salt.into()),
+        }
+    }).collect())
+}
+
+// Creates the claim circuit of the spec from the played cards (suite,
+// rank, salt)
+pub fn create_claim_circuit(spec: &DeckSpec, claimed_rank: u64, cards: &[(u64, u64, Fp)])
+    -> Result<ClaimCircuit<Fp>, CardError> {
+
+    Ok(ClaimCircuit {
+        spec: *spec,
+        claimed_rank: Value::known(Fp::from(claimed_rank).into()),
+        count: Value::known(Fp::from(cards.len() as u64).into()),
+        cards: played_cards(spec, claimed_rank, cards)?,
+    })
+}
+
+// Formats the public inputs (claimed rank, count, commitments),
+// the unused commitment slots are 0
+pub fn create_claim_public_inputs(spec: &DeckSpec, claimed_rank: u64, commitments: &[Fp])
+    -> Result<Vec<Fp>, CardError> {
+
+    check_played_count(spec, commitments.len())?;
+
+    let mut public_inputs = vec![Fp::from(claimed_rank),
+        Fp::from(commitments.len() as u64)];
+    public_inputs.extend((0..played_slots(spec)).map(|i| {
+        commitments.get(i).copied().unwrap_or(Fp::ZERO)
+    }));
+
+    Ok(public_inputs)
+}
+
+#[cfg(test)]
+fn run_claim(claimed_rank: u64, cards: &[(u64, u64, Fp)], commitments: &[Fp])
+    -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    run_spec_claim(&DeckSpec::standard(), claimed_rank, cards, commitments)
+}
+
+#[cfg(test)]
+fn run_spec_claim(spec: &DeckSpec, claimed_rank: u64, cards: &[(u64, u64, Fp)],
+    commitments: &[Fp]) -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 10;
+
+    let circuit = create_claim_circuit(spec, claimed_rank, cards).unwrap();
+    let public_inputs = create_claim_public_inputs(spec, claimed_rank, commitments).unwrap();
+    MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+}
+
+#[test]
+fn test_honest_claim() {
+    let cards = [(1, 7, Fp::from(11)), (3, 7, Fp::from(12)),
+                (4, 7, Fp::from(13)), (2, 7, Fp::from(14))];
+
+    for n in 1..=played_slots(&DeckSpec::standard()) {
+        let played = &cards[..n];
+        assert_eq!(run_claim(7, played, &card_commitments(played)), Ok(()));
+    }
+}
+
+#[test]
+fn test_two_deck_claim() {
+    // Every 7 of two decks
+    let spec = DeckSpec::new(2, 0).unwrap();
+    let cards: Vec<(u64, u64, Fp)> = (0..8)
+        .map(|i| (i % 4 + 1, 7, Fp::from(11 + i)))
+        .collect();
+    assert_eq!(run_spec_claim(&spec, 7, &cards, &card_commitments(&cards)), Ok(()));
+
+    // A single deck has no room for them
+    let standard = DeckSpec::standard();
+    assert!(matches!(create_claim_circuit(&standard, 7, &cards),
+        Err(CardError::PlayedCount(8))));
+    assert!(matches!(create_claim_public_inputs(&standard, 7, &card_commitments(&cards)),
+        Err(CardError::PlayedCount(8))));
+}
+
+#[test]
+fn test_wild_jokers() {
+    let spec = DeckSpec::new(1, 2).unwrap();
+    let cards = [(3, 4, Fp::from(11)), (JOKER_SUITE, JOKER_RANK, Fp::from(12)),
+                (1, 4, Fp::from(13))];
+    let commitments = card_commitments(&cards);
+    assert_eq!(run_spec_claim(&spec, 4, &cards, &commitments), Ok(()));
+
+    // A deck without jokers has no joker to play
+    assert!(run_claim(4, &cards, &commitments).is_err());
+}
+
+#[test]
+fn test_bluff_claim_fails() {
+    // One of the cards is a King, not a 7
+    let cards = [(1, 7, Fp::from(11)), (3, 13, Fp::from(12))];
+    assert!(run_claim(7, &cards, &card_commitments(&cards)).is_err());
+}
+
+#[test]
+fn test_claim_count_and_commitments() {
+    let cards = [(1, 2, Fp::from(11)), (3, 2, Fp::from(12))];
+    let commitments = card_commitments(&cards);
+
+    // Fewer cards announced than played
+    let spec = DeckSpec::standard();
+    let circuit = create_claim_circuit(&spec, 2, &cards).unwrap();
+    let mut public_inputs = create_claim_public_inputs(&spec, 2, &commitments).unwrap();
+    public_inputs[PLAYED_COUNT_ROW] = Fp::from(1);
+    let prover = MockProver::run(10, &circuit, vec![public_inputs]).unwrap();
+    assert!(prover.verify().is_err());
+
+    // Commitment to a different card
+    let other = card_commitments(&[(1, 2, Fp::from(11)), (4, 2, Fp::from(12))]);
+    assert!(run_claim(2, &cards, &other).is_err());
+}
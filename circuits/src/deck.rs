@@ -0,0 +1,582 @@
+use std::marker::PhantomData;
+use halo2_proofs::{
+    circuit::{Layouter, Value, SimpleFloorPlanner},
+    plonk::{Instance, Assigned, Column, ConstraintSystem, Error, Circuit},
+    pasta::Fp,
+};
+#[cfg(test)]
+use halo2_proofs::dev::MockProver;
+
+use crate::card::{CardChip, CardConfig, CardConstrained, CardError, configure_card, commit_card};
+use crate::permutation::{CardCells, Cards, PermutationChip, PermutationConfig};
+use crate::merkle::{MerkleChip, MerkleConfig, merkle_root, merkle_path};
+use crate::poseidon::{PoseidonChip, PoseidonField, Word};
+use crate::qty::QTY_MAX;
+use crate::rank::{RANK_MIN, RANK_MAX};
+use crate::suite::{SUITE_MIN, SUITE_MAX};
+
+// Cards in a standard deck
+pub const DECK_SIZE: usize = 52;
+
+// A joker is a card of its own suite, after the standard suites
+pub const JOKER_SUITE: u64 = SUITE_MAX + 1;
+pub const JOKER_RANK: u64 = RANK_MIN;
+
+// Depth of the smallest tree over `size` cards
+pub const fn deck_depth(size: usize) -> usize {
+    (usize::BITS - size.saturating_sub(1).leading_zeros()) as usize
+}
+
+// The cards a table plays with: a number of standard decks shuffled
+// together and jokers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeckSpec {
+    decks: u64,
+    jokers: u64,
+}
+
+impl DeckSpec {
+    pub fn new(decks: u64, jokers: u64) -> Result<Self, CardError> {
+        if decks == 0 {
+            return Err(CardError::InvalidSpec { decks, jokers });
+        }
+        Ok(DeckSpec { decks, jokers })
+    }
+
+    pub const fn standard() -> Self {
+        DeckSpec { decks: 1, jokers: 0 }
+    }
+
+    pub fn decks(&self) -> u64 {
+        self.decks
+    }
+
+    pub fn jokers(&self) -> u64 {
+        self.jokers
+    }
+
+    // Number of cards
+    pub const fn size(&self) -> usize {
+        DECK_SIZE * self.decks as usize + self.jokers as usize
+    }
+
+    // Most cards of a rank, 4 per deck
+    pub const fn qty_max(&self) -> u64 {
+        QTY_MAX * self.decks
+    }
+
+    // Highest suite of a card, the joker suite if there are jokers
+    pub fn suite_max(&self) -> u64 {
+        if self.jokers > 0 { JOKER_SUITE } else { SUITE_MAX }
+    }
+
+    // Every (suite, rank) pair of the cards in canonical order, the
+    // decks then the jokers
+    pub fn cards(&self) -> Vec<(u64, u64)> {
+        let mut cards: Vec<(u64, u64)> = (0..self.decks).flat_map(|_| standard_deck()).collect();
+        cards.extend((0..self.jokers).map(|_| (JOKER_SUITE, JOKER_RANK)));
+        cards
+    }
+}
+
+impl Default for DeckSpec {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+// Row of the deck root in the public input column, followed by the
+// position of a card if it is revealed
+pub const DECK_ROOT_ROW: usize = 0;
+pub const POSITION_ROW: usize = 1;
+
+// The deck proof exposes the card commitments after the root, in
+// deck order, so the leaves stored on chain are bound to it
+pub const LEAVES_ROW: usize = 1;
+
+// Every (suite, rank) pair of the standard deck, in canonical order
+pub fn standard_deck() -> Vec<(u64, u64)> {
+    (SUITE_MIN..=SUITE_MAX).flat_map(|suite| {
+        (RANK_MIN..=RANK_MAX).map(move |rank| (suite, rank))
+    }).collect()
+}
+
+// Commits to a card of the deck (suite, rank)
+pub fn commit_deck_card(suite: u64, rank: u64, salt: Fp) -> Fp {
+    commit_card(1, suite, rank, salt)
+}
+
+// The commitments of the cards (suite, rank, salt), in order
+pub fn card_commitments(cards: &[(u64, u64, Fp)]) -> Vec<Fp> {
+    cards.iter().map(|(suite, rank, salt)| commit_deck_card(*suite, *rank, *salt)).collect()
+}
+
+// The deck root, a Merkle tree over the card commitments in deck
+// order. Each commitment is the leaf of its sequence id.
+pub fn deck_root(commitments: &[Fp]) -> Result<Fp, CardError> {
+    merkle_root(commitments, deck_depth(commitments.len()))
+}
+
+// The authentication path of the card at `position` in the deck
+pub fn deck_path(commitments: &[Fp], position: usize) -> Result<Vec<Fp>, CardError> {
+    merkle_path(commitments, deck_depth(commitments.len()), position)
+}
+
+#[derive(Debug, Clone)]
+pub struct DeckConfig<F: PoseidonField> {
+    card: CardConfig<F>,
+    merkle: MerkleConfig<F>,
+    permutation: PermutationConfig,
+    instance: Column<Instance>,
+}
+
+// The (suite, rank) cells of the cards, their commitments and the root
+type CommittedDeck<F> = (Vec<CardCells<F>>, Vec<Word<F>>, Word<F>);
+
+// The card of a membership proof, the deck root and its position
+type MemberCard<F> = (CardConstrained<F>, Word<F>, Word<F>);
+
+#[derive(Debug, Clone)]
+pub(crate) struct DeckChip<F: PoseidonField> {
+    config: DeckConfig<F>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> DeckChip<F> {
+
+    pub fn construct(config: DeckConfig<F>) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>, card: CardConfig<F>,
+                instance: Column<Instance>) -> DeckConfig<F> {
+
+        let merkle = MerkleChip::configure(meta, card.poseidon().clone());
+        let permutation = PermutationChip::configure(meta);
+        meta.enable_equality(instance);
+
+        DeckConfig {
+            card,
+            merkle,
+            permutation,
+            instance,
+        }
+    }
+
+    pub fn load_tables(&self, layouter: impl Layouter<F>, spec: &DeckSpec)
+        -> Result<(), Error> {
+
+        CardChip::construct(self.config.card.clone()).load_tables(layouter, spec)
+    }
+
+    // Commits to a single card of the deck
+    fn assign_card(&self, mut layouter: impl Layouter<F>, card: &DeckCard<F>)
+        -> Result<CardConstrained<F>, Error> {
+
+        let card_chip = CardChip::construct(self.config.card.clone());
+        let cell = card_chip.assign(layouter.namespace(|| "Card"),
+            Value::known(F::ONE.into()), card.suite, card.rank, card.salt)?;
+        layouter.assign_region(|| "single card", |mut region| {
+            region.constrain_constant(cell.qty.cell(), F::ONE)
+        })?;
+
+        Ok(cell)
+    }
+
+    // Commits each card of the deck and builds the deck tree over the
+    // commitments
+    pub fn commit(&self, mut layouter: impl Layouter<F>, cards: &[DeckCard<F>])
+        -> Result<CommittedDeck<F>, Error> {
+
+        let mut assigned = Vec::with_capacity(cards.len());
+        let mut commitments = Vec::with_capacity(cards.len());
+        for (i, card) in cards.iter().enumerate() {
+            let cell = self.assign_card(
+                layouter.namespace(|| format!("Deck card {}", i)), card)?;
+            commitments.push(cell.commitment);
+            assigned.push((cell.suite, cell.rank));
+        }
+
+        let root = self.merkle().root(layouter.namespace(|| "Deck root"),
+            &commitments, deck_depth(cards.len()))?;
+
+        Ok((assigned, commitments, root))
+    }
+
+    // Commits to the card and recomputes the deck root from its
+    // position and authentication path. Returns the card, the root and
+    // the position.
+    pub fn member(&self, mut layouter: impl Layouter<F>, card: &DeckCard<F>,
+        position: Value<u64>, path: &[Value<Assigned<F>>])
+        -> Result<MemberCard<F>, Error> {
+
+        let cell = self.assign_card(layouter.namespace(|| "Member card"), card)?;
+
+        let (root, position) = self.merkle().path(layouter.namespace(|| "Member path"),
+            &cell.commitment, position, path)?;
+
+        Ok((cell, root, position))
+    }
+
+    fn merkle(&self) -> MerkleChip<F> {
+        MerkleChip::construct(self.config.merkle.clone())
+    }
+
+    pub fn poseidon(&self) -> PoseidonChip<F> {
+        PoseidonChip::construct(self.config.card.poseidon().clone())
+    }
+
+    pub fn permutation(&self) -> PermutationChip<F> {
+        PermutationChip::construct(self.config.permutation.clone())
+    }
+
+    pub fn expose_public(&self, mut layouter: impl Layouter<F>, root: &Word<F>,
+        row: usize) -> Result<(), Error> {
+
+        layouter.constrain_instance(root.cell(), self.config.instance, row)
+    }
+
+    pub fn assign(&self, mut layouter: impl Layouter<F>, spec: &DeckSpec,
+        cards: &[DeckCard<F>]) -> Result<(), Error> {
+
+        if cards.len() != spec.size() {
+            return Err(Error::Synthesis);
+        }
+        self.load_tables(layouter.namespace(|| "Card Tables"), spec)?;
+
+        let (assigned, commitments, root) = self.commit(layouter.namespace(|| "Deck"), cards)?;
+
+        // The challenge is derived from the deck root
+        let challenge = self.poseidon().hash(layouter.namespace(|| "Deck challenge"),
+            std::array::from_ref(&root))?;
+
+        let canonical = spec.cards();
+        self.permutation().assign(layouter.namespace(|| "Deck check"), &challenge,
+            Cards::Assigned(&assigned), Cards::Constant(&canonical))?;
+
+        self.expose_public(layouter.namespace(|| "Deck root"), &root, DECK_ROOT_ROW)?;
+        for (i, commitment) in commitments.iter().enumerate() {
+            self.expose_public(layouter.namespace(|| format!("Leaf {}", i)),
+                commitment, LEAVES_ROW + i)?;
+        }
+
+        Ok(())
+    }
+}
+
+// A committed card of the deck
+#[derive(Debug, Clone, Copy)]
+pub struct DeckCard<F: PoseidonField> {
+    suite: Value<Assigned<F>>,
+    rank: Value<Assigned<F>>,
+    salt: Value<Assigned<F>>,
+}
+
+impl<F: PoseidonField> Default for DeckCard<F> {
+    fn default() -> Self {
+        Self {
+            suite: Value::unknown(),
+            rank: Value::unknown(),
+            salt: Value::unknown(),
+        }
+    }
+}
+
+// Proves that the committed cards under the deck root are exactly
+// the cards of the spec, every (suite, rank) once per deck
+pub struct DeckCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    cards: Vec<DeckCard<F>>,
+}
+
+impl<F: PoseidonField> DeckCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self { spec, cards: vec![DeckCard::default(); spec.size()] }
+    }
+}
+
+impl<F: PoseidonField> Default for DeckCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for DeckCircuit<F> {
+
+    type Config = DeckConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let card = configure_card(meta, instance);
+
+        DeckChip::configure(meta, card, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let chip: DeckChip<F> = DeckChip::construct(config);
+
+        chip.assign(layouter.namespace(|| "Deck Assign"), &self.spec, &self.cards)
+    }
+}
+
+// The cards (suite, rank, salt) as circuit witnesses
+pub(crate) fn card_witnesses(cards: &[(u64, u64, Fp)]) -> Vec<DeckCard<Fp>> {
+    cards.iter().map(|(suite, rank, salt)| DeckCard {
+        suite: Value::known(Fp::from(*suite).into()),
+        rank: Value::known(Fp::from(*rank).into()),
+        salt: Value::known((*salt).into()),
+    }).collect()
+}
+
+// Creates the deck circuit of the spec from the cards (suite, rank,
+// salt) in deck order
+pub fn create_deck_circuit(spec: &DeckSpec, cards: &[(u64, u64, Fp)])
+    -> Result<DeckCircuit<Fp>, CardError> {
+
+    if cards.len() != spec.size() {
+        return Err(CardError::DeckSize { expected: spec.size(), found: cards.len() });
+    }
+
+    Ok(DeckCircuit { spec: *spec, cards: card_witnesses(cards) })
+}
+
+// Formats the public inputs, the deck root followed by the card
+// commitments
+pub fn create_deck_public_inputs(cards: &[(u64, u64, Fp)]) -> Result<Vec<Fp>, CardError> {
+    let commitments = card_commitments(cards);
+
+    Ok([vec![deck_root(&commitments)?], commitments].concat())
+}
+
+// Proves that a private card is the leaf at a position in the deck
+// tree under the public root, without revealing the card. The position
+// is only revealed if `reveal_position` is set.
+pub struct MembershipCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    card: DeckCard<F>,
+    position: Value<u64>,
+    path: Vec<Value<Assigned<F>>>,
+    reveal_position: bool,
+}
+
+impl<F: PoseidonField> MembershipCircuit<F> {
+    // Circuit of the spec without witnesses, for the key generation
+    pub fn empty(spec: DeckSpec, reveal_position: bool) -> Self {
+        Self {
+            spec,
+            card: DeckCard::default(),
+            position: Value::unknown(),
+            path: vec![Value::unknown(); deck_depth(spec.size())],
+            reveal_position,
+        }
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for MembershipCircuit<F> {
+
+    type Config = DeckConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec, self.reveal_position)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let card = configure_card(meta, instance);
+
+        DeckChip::configure(meta, card, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let chip: DeckChip<F> = DeckChip::construct(config);
+        chip.load_tables(layouter.namespace(|| "Card Tables"), &self.spec)?;
+
+        let (_, root, position) = chip.member(layouter.namespace(|| "Membership"),
+            &self.card, self.position, &self.path)?;
+
+        chip.expose_public(layouter.namespace(|| "Deck root"), &root, DECK_ROOT_ROW)?;
+        if self.reveal_position {
+            chip.expose_public(layouter.namespace(|| "Position"), &position,
+                POSITION_ROW)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Creates the membership circuit for the card at `position` of the
+// deck of the spec (suite, rank, salt)
+pub fn create_membership_circuit(spec: &DeckSpec, cards: &[(u64, u64, Fp)],
+    position: usize, reveal_position: bool) -> Result<MembershipCircuit<Fp>, CardError> {
+
+    if cards.len() != spec.size() {
+        return Err(CardError::DeckSize { expected: spec.size(), found: cards.len() });
+    }
+    let (suite, rank, salt) = *cards.get(position).ok_or(CardError::NoSuchLeaf(position))?;
+    let path = deck_path(&card_commitments(cards), position)?;
+
+    Ok(MembershipCircuit {
+        spec: *spec,
+        card: DeckCard {
+            suite: Value::known(Fp::from(suite).into()),
+            rank: Value::known(Fp::from(rank).into()),
+            salt: Value::known(salt.into()),
+        },
+        position: Value::known(position as u64),
+        path: path.into_iter().map(|sibling| Value::known(sibling.into())).collect(),
+        reveal_position,
+    })
+}
+
+// Formats the public inputs, the deck root and the position if revealed
+pub fn create_membership_public_inputs(root: Fp, position: Option<usize>) -> Vec<Fp> {
+    let mut public_inputs = vec![root];
+    if let Some(position) = position {
+        public_inputs.push(Fp::from(position as u64));
+    }
+
+    public_inputs
+}
+
+// Test cards (suite, rank) with distinct salts
+#[cfg(test)]
+pub(crate) fn salted(cards: &[(u64, u64)]) -> Vec<(u64, u64, Fp)> {
+    cards.iter().enumerate()
+        .map(|(i, (suite, rank))| (*suite, *rank, Fp::from(1000 + i as u64)))
+        .collect()
+}
+
+#[cfg(test)]
+fn run_deck(cards: &[(u64, u64, Fp)]) -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+    const K: u32 = 14;
+
+    let circuit = create_deck_circuit(&DeckSpec::standard(), cards).unwrap();
+    let public_inputs = create_deck_public_inputs(cards).unwrap();
+    MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+}
+
+#[test]
+fn test_standard_deck() {
+    // Any order of the standard deck is valid
+    let mut deck = standard_deck();
+    deck.reverse();
+    deck.swap(3, 40);
+    assert_eq!(run_deck(&salted(&deck)), Ok(()));
+}
+
+#[test]
+fn test_repeated_card() {
+    // Queen of Hearts twice, no King of Flowers
+    let mut deck = standard_deck();
+    deck[DECK_SIZE - 1] = (1, 12);
+    assert!(run_deck(&salted(&deck)).is_err());
+}
+
+#[test]
+fn test_deck_root_binds_cards() {
+    let deck = salted(&standard_deck());
+    let circuit = create_deck_circuit(&DeckSpec::standard(), &deck).unwrap();
+
+    let mut other = deck.clone();
+    other[0].2 += Fp::from(1);
+    let public_inputs = create_deck_public_inputs(&other).unwrap();
+    let prover = MockProver::run(14, &circuit, vec![public_inputs]).unwrap();
+    assert!(prover.verify().is_err());
+
+    // The leaves are bound in deck order
+    let mut public_inputs = create_deck_public_inputs(&deck).unwrap();
+    public_inputs.swap(LEAVES_ROW, LEAVES_ROW + 1);
+    let prover = MockProver::run(14, &circuit, vec![public_inputs]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn test_two_decks_with_jokers() {
+    const K: u32 = 15;
+
+    let spec = DeckSpec::new(2, 4).unwrap();
+    assert_eq!(spec.size(), 108);
+    assert_eq!(deck_depth(spec.size()), 7);
+
+    let run = |cards: &[(u64, u64, Fp)]| {
+        let circuit = create_deck_circuit(&spec, cards).unwrap();
+        let public_inputs = create_deck_public_inputs(cards).unwrap();
+        MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+    };
+
+    // Every card twice, in any order
+    let mut deck = spec.cards();
+    deck.reverse();
+    assert_eq!(run(&salted(&deck)), Ok(()));
+
+    // A third Queen of Hearts in place of a joker
+    deck[0] = (1, 12);
+    assert!(run(&salted(&deck)).is_err());
+
+    // The cards must be as many as the deck holds, of at least a deck
+    assert!(matches!(create_deck_circuit(&spec, &salted(&deck[1..])),
+        Err(CardError::DeckSize { expected: 108, found: 107 })));
+    assert!(matches!(DeckSpec::new(0, 2), Err(CardError::InvalidSpec { .. })));
+}
+
+#[cfg(test)]
+fn run_membership(circuit: &MembershipCircuit<Fp>, public_inputs: Vec<Fp>)
+    -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 10;
+
+    MockProver::run(K, circuit, vec![public_inputs]).unwrap().verify()
+}
+
+#[test]
+fn test_membership() {
+    // A standard deck and a deck of two decks with jokers, a deeper tree
+    for spec in [DeckSpec::standard(), DeckSpec::new(2, 4).unwrap()] {
+        let deck = salted(&spec.cards());
+        let root = create_deck_public_inputs(&deck).unwrap()[DECK_ROOT_ROW];
+
+        for position in [0, 17, spec.size() - 1] {
+            let hidden = create_membership_circuit(&spec, &deck, position, false).unwrap();
+            assert_eq!(run_membership(&hidden,
+                create_membership_public_inputs(root, None)), Ok(()));
+
+            let revealed = create_membership_circuit(&spec, &deck, position, true).unwrap();
+            assert_eq!(run_membership(&revealed,
+                create_membership_public_inputs(root, Some(position))), Ok(()));
+            assert!(run_membership(&revealed,
+                create_membership_public_inputs(root, Some(position + 1))).is_err());
+        }
+    }
+}
+
+#[test]
+fn test_membership_rejects_foreign_card() {
+    let spec = DeckSpec::standard();
+    let deck = salted(&standard_deck());
+    let root = create_deck_public_inputs(&deck).unwrap()[DECK_ROOT_ROW];
+
+    // Same position and path, but a card that was not dealt there
+    let mut forged = deck.clone();
+    forged[5].1 = 7;
+    let circuit = create_membership_circuit(&spec, &forged, 5, false).unwrap();
+    assert!(run_membership(&circuit,
+        create_membership_public_inputs(root, None)).is_err());
+
+    // A position past the deck or a deck of another size
+    assert!(matches!(create_membership_circuit(&spec, &deck, DECK_SIZE, false),
+        Err(CardError::NoSuchLeaf(DECK_SIZE))));
+    assert!(matches!(create_membership_circuit(&spec, &deck[1..], 5, false),
+        Err(CardError::DeckSize { .. })));
+    assert!(matches!(create_deck_public_inputs(&[]), Err(CardError::TreeSize { leaves: 0, .. })));
+}
@@ -0,0 +1,216 @@
+use ff::{Field, FromUniformBytes, PrimeField};
+use group::{Group, GroupEncoding};
+use rand_core::RngCore;
+#[cfg(test)]
+use rand_core::OsRng;
+
+use crate::mental_poker::{Point, Scalar};
+
+// Distributed generation of the table key. Each player picks a secret
+// share and publishes share * G with a Schnorr proof of knowledge,
+// bound to the session and the player. The table key is the sum of
+// the public shares, nobody knows its secret.
+
+// Domain of the Schnorr challenges
+const SCHNORR_PERSONAL: &[u8; 16] = b"bbbluff_Schnorr_";
+
+// Size of a serialized key share, player | public share | proof
+pub const KEY_SHARE_BYTES: usize = 4 + 32 + 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DkgError {
+    // The proof of knowledge of the player's share does not verify
+    InvalidProof(u32),
+    // The player contributed more than one share
+    DuplicatePlayer(u32),
+    // The public share is the identity or repeats another player's
+    WeakShare(u32),
+    // Malformed serialized share
+    Encoding,
+    // No shares to aggregate
+    NoShares,
+}
+
+// Fiat-Shamir challenge of the proof for `player` in `session`
+fn challenge(session: &[u8], player: u32, public: &Point, commitment: &Point) -> Scalar {
+    let bytes: [u8; 64] = blake2b_simd::Params::new()
+        .hash_length(64)
+        .personal(SCHNORR_PERSONAL)
+        .to_state()
+        .update(&(session.len() as u64).to_le_bytes())
+        .update(session)
+        .update(&player.to_le_bytes())
+        .update(&public.to_bytes())
+        .update(&commitment.to_bytes())
+        .finalize()
+        .as_bytes()
+        .try_into()
+        .unwrap();
+
+    Scalar::from_uniform_bytes(&bytes)
+}
+
+// Proof of knowledge of the secret of a public share
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchnorrProof {
+    commitment: Point,
+    response: Scalar,
+}
+
+// A player's public contribution to the table key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyShare {
+    pub player: u32,
+    pub public: Point,
+    proof: SchnorrProof,
+}
+
+impl KeyShare {
+    // Picks the secret share of `player` and proves knowledge of it
+    pub fn generate(player: u32, session: &[u8], mut rng: impl RngCore)
+        -> (Scalar, KeyShare) {
+
+        let secret = Scalar::random(&mut rng);
+        let public = Point::generator() * secret;
+
+        let k = Scalar::random(&mut rng);
+        let commitment = Point::generator() * k;
+        let c = challenge(session, player, &public, &commitment);
+        let proof = SchnorrProof { commitment, response: k + c * secret };
+
+        (secret, KeyShare { player, public, proof })
+    }
+
+    pub fn verify(&self, session: &[u8]) -> bool {
+        let c = challenge(session, self.player, &self.public, &self.proof.commitment);
+
+        Point::generator() * self.proof.response ==
+            self.proof.commitment + self.public * c
+    }
+
+    pub fn to_bytes(&self) -> [u8; KEY_SHARE_BYTES] {
+        let mut bytes = [0_u8; KEY_SHARE_BYTES];
+        bytes[..4].copy_from_slice(&self.player.to_le_bytes());
+        bytes[4..36].copy_from_slice(&self.public.to_bytes());
+        bytes[36..68].copy_from_slice(&self.proof.commitment.to_bytes());
+        bytes[68..].copy_from_slice(&self.proof.response.to_repr());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DkgError> {
+        let bytes: &[u8; KEY_SHARE_BYTES] = bytes.try_into()
+            .map_err(|_| DkgError::Encoding)?;
+
+        let point = |b: &[u8]| -> Result<Point, DkgError> {
+            Option::from(Point::from_bytes(b.try_into().unwrap()))
+                .ok_or(DkgError::Encoding)
+        };
+        let response = Option::from(Scalar::from_repr(bytes[68..].try_into().unwrap()))
+            .ok_or(DkgError::Encoding)?;
+
+        Ok(KeyShare {
+            player: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            public: point(&bytes[4..36])?,
+            proof: SchnorrProof { commitment: point(&bytes[36..68])?, response },
+        })
+    }
+}
+
+// The joint key of the table and the public share of each player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableKey {
+    key: Point,
+    shares: Vec<(u32, Point)>,
+}
+
+impl TableKey {
+    // Checks every contribution and sums the public shares. A rogue
+    // key chosen to cancel the others has no known secret, so it
+    // cannot come with a valid proof.
+    pub fn aggregate(session: &[u8], shares: &[KeyShare]) -> Result<Self, DkgError> {
+        if shares.is_empty() {
+            return Err(DkgError::NoShares);
+        }
+
+        let mut table = TableKey { key: Point::identity(), shares: vec![] };
+        for share in shares {
+            if table.public_share(share.player).is_some() {
+                return Err(DkgError::DuplicatePlayer(share.player));
+            }
+            if bool::from(share.public.is_identity()) ||
+                table.shares.iter().any(|(_, public)| *public == share.public) {
+                return Err(DkgError::WeakShare(share.player));
+            }
+            if !share.verify(session) {
+                return Err(DkgError::InvalidProof(share.player));
+            }
+
+            table.key += share.public;
+            table.shares.push((share.player, share.public));
+        }
+
+        Ok(table)
+    }
+
+    pub fn key(&self) -> &Point {
+        &self.key
+    }
+
+    pub fn public_share(&self, player: u32) -> Option<&Point> {
+        self.shares.iter()
+            .find(|(p, _)| *p == player)
+            .map(|(_, public)| public)
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = u32> + '_ {
+        self.shares.iter().map(|(player, _)| *player)
+    }
+}
+
+#[cfg(test)]
+const SESSION: &[u8] = b"table 1";
+
+#[test]
+fn test_aggregate_table_key() {
+    let (secrets, shares): (Vec<Scalar>, Vec<KeyShare>) = (0..4)
+        .map(|player| KeyShare::generate(player, SESSION, OsRng))
+        .unzip();
+
+    let table = TableKey::aggregate(SESSION, &shares).unwrap();
+    let secret: Scalar = secrets.iter().sum();
+    assert_eq!(*table.key(), Point::generator() * secret);
+    assert_eq!(table.players().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+    assert_eq!(table.public_share(2), Some(&shares[2].public));
+
+    // Shares round trip through bytes
+    for share in &shares {
+        assert_eq!(KeyShare::from_bytes(&share.to_bytes()), Ok(*share));
+    }
+}
+
+#[test]
+fn test_rogue_key_rejected() {
+    let (_, honest) = KeyShare::generate(0, SESSION, OsRng);
+
+    // The rogue key cancels the honest share, so the attacker alone
+    // would know the table secret. Its proof cannot verify.
+    let (secret, mut rogue) = KeyShare::generate(1, SESSION, OsRng);
+    rogue.public = Point::generator() * secret - honest.public;
+    assert_eq!(TableKey::aggregate(SESSION, &[honest, rogue]),
+        Err(DkgError::InvalidProof(1)));
+
+    // Proofs are bound to the session and the player
+    let (_, other) = KeyShare::generate(1, b"table 2", OsRng);
+    assert_eq!(TableKey::aggregate(SESSION, &[honest, other]),
+        Err(DkgError::InvalidProof(1)));
+    let mut moved = other;
+    moved.player = 2;
+    assert!(!moved.verify(b"table 2"));
+
+    assert_eq!(TableKey::aggregate(SESSION, &[honest, honest]),
+        Err(DkgError::DuplicatePlayer(0)));
+    let mut copied = honest;
+    copied.player = 1;
+    assert_eq!(TableKey::aggregate(SESSION, &[honest, copied]),
+        Err(DkgError::WeakShare(1)));
+}
@@ -0,0 +1,460 @@
+use std::fmt;
+use ff::PrimeField;
+use halo2_proofs::{
+    pasta::{Fp, EqAffine},
+    plonk::{Circuit, ConstraintSystem, VerifyingKey},
+};
+#[cfg(test)]
+use halo2_proofs::plonk::keygen_vk;
+#[cfg(test)]
+use crate::card::{empty_circuit, generate_setup_params};
+
+// A proof with what it was generated for: the circuit, its size k,
+// the verifying key and the public inputs. Serialized as
+//
+// magic | version | id len | circuit id | k | vk fingerprint |
+// input count | public inputs | proof len | proof
+//
+// in little endian, or as JSON with hex encoded bytes.
+
+pub const ENVELOPE_MAGIC: [u8; 4] = *b"BBPF";
+pub const ENVELOPE_VERSION: u16 = 1;
+
+// Value of the format key of the JSON encoding
+const JSON_FORMAT: &str = "bbbluff-proof";
+
+// Domains of the vk and constraint system fingerprints
+const VK_PERSONAL: &[u8; 16] = b"bbbluff_VK_print";
+const CS_PERSONAL: &[u8; 16] = b"bbbluff_CS_print";
+
+// Circuit id of the card proofs
+pub const CARD_CIRCUIT: &str = "card";
+
+// Longest circuit id, its length is encoded in a byte
+pub const MAX_CIRCUIT_ID: usize = u8::MAX as usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvelopeError {
+    // The bytes don't start with ENVELOPE_MAGIC
+    Magic,
+    // The version read, as wide as the JSON number it may come from
+    UnsupportedVersion(u64),
+    // The circuit id is empty, longer than MAX_CIRCUIT_ID or not of
+    // [a-z0-9_-]
+    InvalidCircuitId(String),
+    // Truncated or trailing bytes, bad hex or JSON, non canonical
+    // field elements
+    Malformed,
+    // The proof is of another circuit, size or key than expected
+    CircuitMismatch { expected: String, found: String },
+    KMismatch { expected: u32, found: u32 },
+    VkMismatch { expected: [u8; 32], found: [u8; 32] },
+    // The proof is of other public inputs than the ones to verify
+    PublicInputsMismatch,
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvelopeError::Magic => write!(f, "not a proof envelope"),
+            EnvelopeError::UnsupportedVersion(version) =>
+                write!(f, "unsupported envelope version {}", version),
+            EnvelopeError::InvalidCircuitId(id) => write!(f, "invalid circuit id {:?}", id),
+            EnvelopeError::Malformed => write!(f, "malformed envelope"),
+            EnvelopeError::CircuitMismatch { expected, found } =>
+                write!(f, "proof of circuit {}, expected {}", found, expected),
+            EnvelopeError::KMismatch { expected, found } =>
+                write!(f, "proof of k {}, expected {}", found, expected),
+            EnvelopeError::VkMismatch { expected, found } =>
+                write!(f, "proof of vk {}, expected {}", to_hex(found), to_hex(expected)),
+            EnvelopeError::PublicInputsMismatch =>
+                write!(f, "proof of other public inputs"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofEnvelope {
+    // 1 to MAX_CIRCUIT_ID bytes of [a-z0-9_-], it is neither
+    // truncated nor escaped when encoded
+    pub circuit_id: String,
+    pub k: u32,
+    pub vk_fingerprint: [u8; 32],
+    pub public_inputs: Vec<Fp>,
+    pub proof: Vec<u8>,
+}
+
+impl ProofEnvelope {
+    // Wraps a proof of `circuit_id` under `vk`
+    pub fn new(circuit_id: &str, vk: &VerifyingKey<EqAffine>,
+            public_inputs: &[Fp], proof: Vec<u8>) -> Self {
+
+        ProofEnvelope {
+            circuit_id: circuit_id.to_string(),
+            k: vk_k(vk),
+            vk_fingerprint: vk_fingerprint(vk),
+            public_inputs: public_inputs.to_vec(),
+            proof,
+        }
+    }
+
+    // Checks the proof is of `circuit_id` under `vk` for `public_inputs`
+    pub fn check(&self, circuit_id: &str, vk: &VerifyingKey<EqAffine>,
+            public_inputs: &[Fp]) -> Result<(), EnvelopeError> {
+
+        if self.circuit_id != circuit_id {
+            return Err(EnvelopeError::CircuitMismatch {
+                expected: circuit_id.to_string(), found: self.circuit_id.clone() });
+        }
+
+        let k = vk_k(vk);
+        if self.k != k {
+            return Err(EnvelopeError::KMismatch { expected: k, found: self.k });
+        }
+
+        let fingerprint = vk_fingerprint(vk);
+        if self.vk_fingerprint != fingerprint {
+            return Err(EnvelopeError::VkMismatch {
+                expected: fingerprint, found: self.vk_fingerprint });
+        }
+
+        if self.public_inputs != public_inputs {
+            return Err(EnvelopeError::PublicInputsMismatch);
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EnvelopeError> {
+        check_circuit_id(&self.circuit_id)?;
+
+        let mut bytes = ENVELOPE_MAGIC.to_vec();
+        bytes.extend_from_slice(&ENVELOPE_VERSION.to_le_bytes());
+        bytes.push(self.circuit_id.len() as u8);
+        bytes.extend_from_slice(self.circuit_id.as_bytes());
+        bytes.extend_from_slice(&self.k.to_le_bytes());
+        bytes.extend_from_slice(&self.vk_fingerprint);
+        bytes.extend_from_slice(&(self.public_inputs.len() as u32).to_le_bytes());
+        for input in &self.public_inputs {
+            bytes.extend_from_slice(&input.to_repr());
+        }
+        bytes.extend_from_slice(&(self.proof.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.proof);
+
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, EnvelopeError> {
+        let mut reader = Reader(bytes);
+
+        if reader.take(ENVELOPE_MAGIC.len())? != ENVELOPE_MAGIC {
+            return Err(EnvelopeError::Magic);
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version.into()));
+        }
+
+        let [id_len] = reader.array()?;
+        let circuit_id = String::from_utf8(reader.take(id_len as usize)?.to_vec())
+            .map_err(|_| EnvelopeError::Malformed)?;
+        check_circuit_id(&circuit_id)?;
+        let k = u32::from_le_bytes(reader.array()?);
+        let vk_fingerprint = reader.array()?;
+
+        let inputs = u32::from_le_bytes(reader.array()?);
+        let public_inputs = (0..inputs)
+            .map(|_| reader.array().and_then(read_fp))
+            .collect::<Result<_, _>>()?;
+
+        let proof_len = u32::from_le_bytes(reader.array()?);
+        let proof = reader.take(proof_len as usize)?.to_vec();
+
+        if !reader.0.is_empty() {
+            return Err(EnvelopeError::Malformed);
+        }
+
+        Ok(ProofEnvelope { circuit_id, k, vk_fingerprint, public_inputs, proof })
+    }
+
+    pub fn to_json(&self) -> Result<String, EnvelopeError> {
+        check_circuit_id(&self.circuit_id)?;
+
+        let inputs: Vec<String> = self.public_inputs.iter()
+            .map(|input| format!("\"{}\"", to_hex(&input.to_repr())))
+            .collect();
+
+        Ok(format!("{{\"format\":\"{}\",\"version\":{},\"circuit\":\"{}\",\"k\":{},\
+            \"vk\":\"{}\",\"public_inputs\":[{}],\"proof\":\"{}\"}}",
+            JSON_FORMAT, ENVELOPE_VERSION, self.circuit_id, self.k,
+            to_hex(&self.vk_fingerprint), inputs.join(","), to_hex(&self.proof)))
+    }
+
+    // Reads the JSON of `to_json`, in any key order and whitespace
+    pub fn from_json(json: &str) -> Result<Self, EnvelopeError> {
+        let mut parser = JsonParser(json.trim_start());
+        let (mut format, mut version, mut circuit_id, mut k) = (None, None, None, None);
+        let (mut vk_fingerprint, mut public_inputs, mut proof) = (None, None, None);
+
+        parser.expect('{')?;
+        loop {
+            let key = parser.string()?;
+            parser.expect(':')?;
+            match key {
+                "format" => format = Some(parser.string()?),
+                "version" => version = Some(parser.number()?),
+                "circuit" => circuit_id = Some(parser.string()?.to_string()),
+                "k" => k = Some(parser.number()?),
+                "vk" => vk_fingerprint = Some(from_hex(parser.string()?)?
+                    .try_into().map_err(|_| EnvelopeError::Malformed)?),
+                "public_inputs" => public_inputs = Some(parser.strings()?.into_iter()
+                    .map(|input| from_hex(input)?.try_into()
+                        .map_err(|_| EnvelopeError::Malformed).and_then(read_fp))
+                    .collect::<Result<_, _>>()?),
+                "proof" => proof = Some(from_hex(parser.string()?)?),
+                _ => return Err(EnvelopeError::Malformed),
+            }
+            if !parser.next_item('}')? {
+                break;
+            }
+        }
+        if !parser.0.trim().is_empty() {
+            return Err(EnvelopeError::Malformed);
+        }
+
+        if format != Some(JSON_FORMAT) {
+            return Err(EnvelopeError::Magic);
+        }
+        let version = version.ok_or(EnvelopeError::Malformed)?;
+        if version != u64::from(ENVELOPE_VERSION) {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+
+        let circuit_id = circuit_id.ok_or(EnvelopeError::Malformed)?;
+        check_circuit_id(&circuit_id)?;
+
+        Ok(ProofEnvelope {
+            circuit_id,
+            k: k.and_then(|k| k.try_into().ok()).ok_or(EnvelopeError::Malformed)?,
+            vk_fingerprint: vk_fingerprint.ok_or(EnvelopeError::Malformed)?,
+            public_inputs: public_inputs.ok_or(EnvelopeError::Malformed)?,
+            proof: proof.ok_or(EnvelopeError::Malformed)?,
+        })
+    }
+}
+
+fn check_circuit_id(id: &str) -> Result<(), EnvelopeError> {
+    let valid = (1..=MAX_CIRCUIT_ID).contains(&id.len()) && id.bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'-');
+
+    if !valid {
+        return Err(EnvelopeError::InvalidCircuitId(id.to_string()));
+    }
+    Ok(())
+}
+
+// Digest of the pinned vk, i.e the constraint system, domain, fixed
+// commitments and permutation. Equal vks have equal fingerprints.
+pub fn vk_fingerprint(vk: &VerifyingKey<EqAffine>) -> [u8; 32] {
+    fingerprint(VK_PERSONAL, &format!("{:?}", vk.pinned()))
+}
+
+// Digest of the pinned constraint system of the circuit, i.e its
+// columns, gates, lookups and permutation columns. Unlike the vk
+// fingerprint it needs no params and is the same for every k.
+pub fn circuit_fingerprint<C: Circuit<Fp>>() -> [u8; 32] {
+    let mut cs = ConstraintSystem::default();
+    C::configure(&mut cs);
+
+    fingerprint(CS_PERSONAL, &format!("{:?}", cs.pinned()))
+}
+
+fn fingerprint(personal: &[u8; 16], pinned: &str) -> [u8; 32] {
+    blake2b_simd::Params::new()
+        .hash_length(32)
+        .personal(personal)
+        .to_state()
+        .update(&(pinned.len() as u64).to_le_bytes())
+        .update(pinned.as_bytes())
+        .finalize()
+        .as_bytes()
+        .try_into()
+        .unwrap()
+}
+
+// The k of the domain of the vk, which has 2^k rows
+fn vk_k(vk: &VerifyingKey<EqAffine>) -> u32 {
+    vk.get_domain().empty_lagrange().len().trailing_zeros()
+}
+
+fn read_fp(repr: [u8; 32]) -> Result<Fp, EnvelopeError> {
+    Option::from(Fp::from_repr(repr)).ok_or(EnvelopeError::Malformed)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, EnvelopeError> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(EnvelopeError::Malformed);
+    }
+
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16)
+            .map_err(|_| EnvelopeError::Malformed))
+        .collect()
+}
+
+// Reads the binary encoding front to back
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], EnvelopeError> {
+        if self.0.len() < len {
+            return Err(EnvelopeError::Malformed);
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], EnvelopeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+// Reads the flat JSON of the envelope, strings hold no escapes
+struct JsonParser<'a>(&'a str);
+
+impl<'a> JsonParser<'a> {
+    fn expect(&mut self, c: char) -> Result<(), EnvelopeError> {
+        self.0 = self.0.strip_prefix(c).ok_or(EnvelopeError::Malformed)?
+            .trim_start();
+        Ok(())
+    }
+
+    // Consumes a ',' and returns true, or `close` and returns false
+    fn next_item(&mut self, close: char) -> Result<bool, EnvelopeError> {
+        if self.expect(',').is_ok() {
+            return Ok(true);
+        }
+        self.expect(close)?;
+        Ok(false)
+    }
+
+    fn string(&mut self) -> Result<&'a str, EnvelopeError> {
+        let rest = self.0.strip_prefix('"').ok_or(EnvelopeError::Malformed)?;
+        let end = rest.find('"').ok_or(EnvelopeError::Malformed)?;
+        let value = &rest[..end];
+        if value.contains('\\') {
+            return Err(EnvelopeError::Malformed);
+        }
+        self.0 = rest[end + 1..].trim_start();
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<u64, EnvelopeError> {
+        let end = self.0.find(|c: char| !c.is_ascii_digit()).unwrap_or(self.0.len());
+        let value = self.0[..end].parse().map_err(|_| EnvelopeError::Malformed)?;
+        self.0 = self.0[end..].trim_start();
+        Ok(value)
+    }
+
+    fn strings(&mut self) -> Result<Vec<&'a str>, EnvelopeError> {
+        let mut values = vec![];
+        self.expect('[')?;
+        if self.expect(']').is_ok() {
+            return Ok(values);
+        }
+        loop {
+            values.push(self.string()?);
+            if !self.next_item(']')? {
+                return Ok(values);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_envelope_encoding() {
+    let vk = keygen_vk(&generate_setup_params(8), &empty_circuit()).unwrap();
+    let envelope = ProofEnvelope::new(CARD_CIRCUIT, &vk,
+        &[Fp::from(7_u64), -Fp::from(1_u64)], vec![1, 2, 3]);
+    assert_eq!(envelope.k, 8);
+
+    let bytes = envelope.to_bytes().unwrap();
+    assert_eq!(ProofEnvelope::from_bytes(&bytes).unwrap(), envelope);
+    assert_eq!(ProofEnvelope::from_json(&envelope.to_json().unwrap()).unwrap(), envelope);
+
+    // Reordered keys and whitespace
+    let json = format!(" {{ \"proof\" : \"010203\", \"public_inputs\": [\"{}\", \"{}\"],\n\
+        \"vk\": \"{}\", \"k\": 8, \"circuit\": \"card\", \"version\": 1,\n\
+        \"format\": \"bbbluff-proof\" }} ",
+        to_hex(&Fp::from(7_u64).to_repr()), to_hex(&(-Fp::from(1_u64)).to_repr()),
+        to_hex(&envelope.vk_fingerprint));
+    assert_eq!(ProofEnvelope::from_json(&json).unwrap(), envelope);
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] ^= 1;
+    assert_eq!(ProofEnvelope::from_bytes(&bad_magic), Err(EnvelopeError::Magic));
+
+    let mut bad_version = bytes.clone();
+    bad_version[4] = 2;
+    assert_eq!(ProofEnvelope::from_bytes(&bad_version),
+        Err(EnvelopeError::UnsupportedVersion(2)));
+
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    for bytes in [&bytes[..bytes.len() - 1], &trailing] {
+        assert_eq!(ProofEnvelope::from_bytes(bytes), Err(EnvelopeError::Malformed));
+    }
+
+    let json = envelope.to_json().unwrap();
+    for json in [&json[..json.len() - 1], &json.replace("\"k\":8,", ""),
+            &json.replace("\"vk\":\"", "\"vk\":\"0")] {
+        assert_eq!(ProofEnvelope::from_json(json), Err(EnvelopeError::Malformed));
+    }
+    assert_eq!(ProofEnvelope::from_json(&json.replace("\"version\":1", "\"version\":3")),
+        Err(EnvelopeError::UnsupportedVersion(3)));
+    assert_eq!(ProofEnvelope::from_json(&json.replace("\"version\":1", "\"version\":65537")),
+        Err(EnvelopeError::UnsupportedVersion(65537)));
+
+    // Ids that would be truncated or need escaping are not encoded
+    let longest = "c".repeat(MAX_CIRCUIT_ID);
+    let long = ProofEnvelope { circuit_id: longest.clone(), ..envelope.clone() };
+    assert_eq!(ProofEnvelope::from_bytes(&long.to_bytes().unwrap()).unwrap(), long);
+    for id in ["", &format!("{}c", longest), "card\",\"k\":9", "Card"] {
+        let bad = ProofEnvelope { circuit_id: id.to_string(), ..envelope.clone() };
+        let invalid = Err(EnvelopeError::InvalidCircuitId(id.to_string()));
+        assert_eq!(bad.to_bytes(), invalid);
+        assert_eq!(bad.to_json(), invalid.clone().map(|_: Vec<u8>| String::new()));
+    }
+    assert_eq!(ProofEnvelope::from_json(&json.replace("\"card\"", "\"Card\"")),
+        Err(EnvelopeError::InvalidCircuitId("Card".to_string())));
+}
+
+#[test]
+fn test_envelope_check() {
+    let vk = keygen_vk(&generate_setup_params(8), &empty_circuit()).unwrap();
+    let inputs = [Fp::from(7_u64)];
+    let envelope = ProofEnvelope::new(CARD_CIRCUIT, &vk, &inputs, vec![]);
+
+    assert_eq!(envelope.check(CARD_CIRCUIT, &vk, &inputs), Ok(()));
+    assert!(matches!(envelope.check("claim", &vk, &inputs),
+        Err(EnvelopeError::CircuitMismatch { .. })));
+    assert_eq!(envelope.check(CARD_CIRCUIT, &vk, &[Fp::from(8_u64)]),
+        Err(EnvelopeError::PublicInputsMismatch));
+
+    // Another size of the same circuit
+    let vk_9 = keygen_vk(&generate_setup_params(9), &empty_circuit()).unwrap();
+    assert_eq!(envelope.check(CARD_CIRCUIT, &vk_9, &inputs),
+        Err(EnvelopeError::KMismatch { expected: 9, found: 8 }));
+
+    let mut other_vk = envelope.clone();
+    other_vk.vk_fingerprint[0] ^= 1;
+    assert!(matches!(other_vk.check(CARD_CIRCUIT, &vk, &inputs),
+        Err(EnvelopeError::VkMismatch { .. })));
+}
@@ -0,0 +1,531 @@
+use crate::card::CardError;
+use crate::deck::DeckSpec;
+#[cfg(test)]
+use crate::deck::DECK_SIZE;
+use crate::qty::QTY_MIN;
+use crate::hand::FOUR_OF_A_KIND;
+use crate::rank::{RANK_MIN, RANK_MAX};
+
+// Rules engine of a Bluff game. It only tracks what every player
+// knows: whose turn it is, the claims, the hand sizes and the pile.
+// Every transition returns the proofs the acting players must publish
+// for the other players to accept it.
+
+pub const MIN_PLAYERS: usize = 2;
+pub const MAX_PLAYERS: usize = 8;
+
+// Jokers added to each deck when they are played
+pub const JOKERS_PER_DECK: usize = 2;
+
+// How the rank of a claim relates to the claims before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankRule {
+    // Every claim on the pile is of the rank of the first one
+    Same,
+    // Each claim is the previous rank + 1, King wraps to Ace. The game
+    // starts with Aces.
+    Sequential,
+    // Each claim is of the rank of the previous claim on the pile or
+    // one away from it, King and Ace being adjacent
+    SameOrAdjacent,
+}
+
+// The house rules a game is played with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleSet {
+    pub ranks: RankRule,
+    // A player may discard a four of a kind on their turn
+    pub four_of_a_kind: bool,
+    // Number of 52 card decks shuffled together and dealt
+    pub decks: usize,
+    // JOKERS_PER_DECK jokers per deck are dealt and stand for any rank
+    // in a claim
+    pub jokers_wild: bool,
+}
+
+impl RuleSet {
+    // Bluff, a pile of a single rank from a single deck
+    pub fn bluff() -> Self {
+        RuleSet { ranks: RankRule::Same, four_of_a_kind: false, decks: 1, jokers_wild: false }
+    }
+
+    // Cheat, claims of the same or an adjacent rank and four of a kind
+    // discards
+    pub fn cheat() -> Self {
+        RuleSet { ranks: RankRule::SameOrAdjacent, four_of_a_kind: true, ..Self::bluff() }
+    }
+
+    // I Doubt It, claims go up the ranks in turn
+    pub fn i_doubt_it() -> Self {
+        RuleSet { ranks: RankRule::Sequential, ..Self::bluff() }
+    }
+
+    // The cards dealt
+    pub fn deck_spec(&self) -> Result<DeckSpec, CardError> {
+        let jokers = if self.jokers_wild { JOKERS_PER_DECK * self.decks } else { 0 };
+        DeckSpec::new(self.decks as u64, jokers as u64)
+    }
+
+    // Most cards put down in a claim, as many as there are of a rank
+    pub fn max_claim(&self) -> Result<u64, CardError> {
+        self.deck_spec().map(|spec| spec.qty_max())
+    }
+
+    // Proofs of a claim by the player: the cards left their hand, they
+    // may be a bluff. The claimed rank is public, the rank rule is
+    // checked on it without a proof.
+    fn claim_proofs(&self, player: usize) -> Vec<RequiredProof> {
+        vec![RequiredProof::Play { player }]
+    }
+
+    // Proofs of the claimant settling a challenge: the cards have the
+    // claimed rank, or they were a bluff
+    fn challenge_proofs(&self, player: usize, bluff: bool) -> Vec<RequiredProof> {
+        if bluff {
+            vec![RequiredProof::Challenge { player }]
+        } else {
+            vec![RequiredProof::Claim { player }]
+        }
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::bluff()
+    }
+}
+
+// The rank after `rank`, King wraps to Ace
+pub fn next_rank(rank: u64) -> u64 {
+    rank % RANK_MAX + RANK_MIN
+}
+
+// Positions in the deck of the cards dealt to the player. The deck
+// of the spec is dealt round robin from player 0.
+pub fn dealt_positions(spec: &DeckSpec, players: usize, player: usize)
+    -> Result<Vec<usize>, GameError> {
+
+    if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&players) {
+        return Err(GameError::PlayerCount(players));
+    }
+    if player >= players {
+        return Err(GameError::NoSuchPlayer(player));
+    }
+
+    Ok((player..spec.size()).step_by(players).collect())
+}
+
+// A proof a player owes the table, named after its circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequiredProof {
+    // `DeckCircuit`, the committed deck holds the cards of the rules
+    Deck,
+    // `DealCircuit`, the player's first hand holds the cards dealt to
+    // them from the deck root
+    Deal { player: usize },
+    // `ClaimCircuit`, every played card has the claimed rank
+    Claim { player: usize },
+    // `PlayCircuit`, the played cards left the player's hand
+    Play { player: usize },
+    // `ChallengeCircuit`, the claimant reveals the played cards bluffed
+    Challenge { player: usize },
+    // `DiscardCircuit`, the player discarded a four of a kind
+    Discard { player: usize },
+    // `PickUpCircuit`, the player added the pile to their hand
+    PickUp { player: usize },
+    // `EmptyHandCircuit`, the player holds no card
+    EmptyHand { player: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameError {
+    // The number of players is out of [2, 8]
+    PlayerCount(usize),
+    // The rules deal no deck
+    InvalidDeck,
+    // The transition is not allowed in the current phase
+    WrongPhase,
+    // The player is not at the table or not the one to act
+    NotYourTurn(usize),
+    // The player is not at the table
+    NoSuchPlayer(usize),
+    // The claimant cannot challenge their own claim
+    OwnClaim(usize),
+    InvalidRank(u64),
+    InvalidQty(u64),
+    // The rules do not allow the transition
+    NotAllowed,
+}
+
+// A claim: `qty` cards of `rank` put down on the pile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Claim {
+    pub player: usize,
+    pub rank: u64,
+    pub qty: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    // Waiting for the deck to be committed, shuffled and dealt
+    Dealing,
+    // The player is to make a claim
+    Turn { player: usize },
+    // The other players may challenge the last claim
+    ChallengeWindow { claim: Claim },
+    // The claimant must prove the challenged cards of the claimed rank
+    // or a bluff
+    Challenged { claim: Claim, challenger: usize },
+    Finished { winner: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    rules: RuleSet,
+    // The cards of the rules, the circuits of the game are sized for it
+    spec: DeckSpec,
+    phase: Phase,
+    hand_sizes: Vec<usize>,
+    // Claims on the pile, the oldest first
+    pile: Vec<Claim>,
+    // Rank of the last claim of the game
+    last_rank: Option<u64>,
+}
+
+impl Game {
+    pub fn new(players: usize, rules: RuleSet) -> Result<Self, GameError> {
+        if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&players) {
+            return Err(GameError::PlayerCount(players));
+        }
+        let spec = rules.deck_spec().map_err(|_| GameError::InvalidDeck)?;
+
+        Ok(Game {
+            rules,
+            spec,
+            phase: Phase::Dealing,
+            hand_sizes: vec![0; players],
+            pile: vec![],
+            last_rank: None,
+        })
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    pub fn spec(&self) -> &DeckSpec {
+        &self.spec
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn players(&self) -> usize {
+        self.hand_sizes.len()
+    }
+
+    pub fn hand_size(&self, player: usize) -> Result<usize, GameError> {
+        self.hand_sizes.get(player).copied().ok_or(GameError::NoSuchPlayer(player))
+    }
+
+    // Number of cards on the pile
+    pub fn pile_size(&self) -> usize {
+        self.pile.iter().map(|claim| claim.qty as usize).sum()
+    }
+
+    pub fn pile(&self) -> &[Claim] {
+        &self.pile
+    }
+
+    // Deals the whole deck round robin, player 0 plays first. Every
+    // player proves their hand against the deck root before the first
+    // claim, the masked deck of `shuffle` is not tied to the root.
+    pub fn deal(&mut self) -> Result<Vec<RequiredProof>, GameError> {
+        if self.phase != Phase::Dealing {
+            return Err(GameError::WrongPhase);
+        }
+
+        let players = self.players();
+        let mut proofs = vec![RequiredProof::Deck];
+        for player in 0..players {
+            self.hand_sizes[player] = dealt_positions(&self.spec, players, player)?.len();
+            proofs.push(RequiredProof::Deal { player });
+        }
+        self.phase = Phase::Turn { player: 0 };
+
+        Ok(proofs)
+    }
+
+    fn check_turn(&self, player: usize) -> Result<(), GameError> {
+        match self.phase {
+            Phase::Turn { player: p } if p == player => Ok(()),
+            Phase::Turn { .. } => Err(GameError::NotYourTurn(player)),
+            _ => Err(GameError::WrongPhase),
+        }
+    }
+
+    // Whether a claim of `rank` follows the earlier claims
+    fn follows(&self, rank: u64) -> bool {
+        match self.rules.ranks {
+            RankRule::Same => self.pile.first().is_none_or(|first| first.rank == rank),
+            RankRule::Sequential => rank == self.last_rank.map_or(RANK_MIN, next_rank),
+            RankRule::SameOrAdjacent => self.pile.last().is_none_or(|last| {
+                rank == last.rank || rank == next_rank(last.rank) ||
+                    next_rank(rank) == last.rank
+            }),
+        }
+    }
+
+    // The player puts `qty` cards down claiming they are of `rank`
+    pub fn claim(&mut self, player: usize, rank: u64, qty: u64)
+        -> Result<Vec<RequiredProof>, GameError> {
+
+        self.check_turn(player)?;
+        if !(RANK_MIN..=RANK_MAX).contains(&rank) || !self.follows(rank) {
+            return Err(GameError::InvalidRank(rank));
+        }
+        if !(QTY_MIN..=self.spec.qty_max()).contains(&qty) ||
+            qty as usize > self.hand_sizes[player] {
+            return Err(GameError::InvalidQty(qty));
+        }
+
+        let claim = Claim { player, rank, qty };
+        self.hand_sizes[player] -= qty as usize;
+        self.pile.push(claim);
+        self.last_rank = Some(rank);
+        self.phase = Phase::ChallengeWindow { claim };
+
+        Ok(self.rules.claim_proofs(player))
+    }
+
+    // The player discards a four of a kind of `rank` before claiming
+    pub fn discard(&mut self, player: usize, rank: u64)
+        -> Result<Vec<RequiredProof>, GameError> {
+
+        self.check_turn(player)?;
+        if !self.rules.four_of_a_kind {
+            return Err(GameError::NotAllowed);
+        }
+        if !(RANK_MIN..=RANK_MAX).contains(&rank) {
+            return Err(GameError::InvalidRank(rank));
+        }
+        // The last cards of a hand are claimed, not discarded
+        if self.hand_sizes[player] <= FOUR_OF_A_KIND {
+            return Err(GameError::InvalidQty(FOUR_OF_A_KIND as u64));
+        }
+
+        self.hand_sizes[player] -= FOUR_OF_A_KIND;
+        Ok(vec![RequiredProof::Discard { player }])
+    }
+
+    // Closes the challenge window without a challenge
+    pub fn accept(&mut self) -> Result<Vec<RequiredProof>, GameError> {
+        let Phase::ChallengeWindow { claim } = self.phase else {
+            return Err(GameError::WrongPhase);
+        };
+
+        Ok(self.next_turn(claim.player, (claim.player + 1) % self.players()))
+    }
+
+    pub fn challenge(&mut self, challenger: usize) -> Result<Vec<RequiredProof>, GameError> {
+        let Phase::ChallengeWindow { claim } = self.phase else {
+            return Err(GameError::WrongPhase);
+        };
+        if challenger >= self.players() {
+            return Err(GameError::NoSuchPlayer(challenger));
+        }
+        if challenger == claim.player {
+            return Err(GameError::OwnClaim(challenger));
+        }
+
+        self.phase = Phase::Challenged { claim, challenger };
+        Ok(vec![])
+    }
+
+    // Settles the challenge, the claimant proves the cards were of the
+    // claimed rank or a bluff. The loser picks up the pile and the
+    // winner plays next.
+    pub fn resolve(&mut self, bluff: bool) -> Result<Vec<RequiredProof>, GameError> {
+        let Phase::Challenged { claim, challenger } = self.phase else {
+            return Err(GameError::WrongPhase);
+        };
+
+        let (loser, winner) = if bluff {
+            (claim.player, challenger)
+        } else {
+            (challenger, claim.player)
+        };
+        self.hand_sizes[loser] += self.pile_size();
+        self.pile.clear();
+
+        let mut proofs = self.rules.challenge_proofs(claim.player, bluff);
+        proofs.push(RequiredProof::PickUp { player: loser });
+        proofs.extend(self.next_turn(claim.player, winner));
+        Ok(proofs)
+    }
+
+    // A claimant left without cards wins, otherwise `next` plays
+    fn next_turn(&mut self, claimant: usize, next: usize) -> Vec<RequiredProof> {
+        if self.hand_sizes[claimant] == 0 {
+            self.phase = Phase::Finished { winner: claimant };
+            return vec![RequiredProof::EmptyHand { player: claimant }];
+        }
+
+        self.phase = Phase::Turn { player: next };
+        vec![]
+    }
+}
+
+#[test]
+fn test_deal() {
+    assert_eq!(Game::new(1, RuleSet::bluff()), Err(GameError::PlayerCount(1)));
+    assert_eq!(Game::new(9, RuleSet::bluff()), Err(GameError::PlayerCount(9)));
+
+    for players in MIN_PLAYERS..=MAX_PLAYERS {
+        let mut game = Game::new(players, RuleSet::bluff()).unwrap();
+        let proofs = game.deal().unwrap();
+        assert_eq!(proofs[0], RequiredProof::Deck);
+        assert_eq!(proofs[1..], (0..players).map(|player| RequiredProof::Deal { player })
+            .collect::<Vec<_>>());
+        assert_eq!((0..players).map(|p| game.hand_size(p).unwrap()).sum::<usize>(), DECK_SIZE);
+        assert_eq!(game.phase(), Phase::Turn { player: 0 });
+        assert_eq!(game.deal(), Err(GameError::WrongPhase));
+    }
+
+    let spec = DeckSpec::standard();
+    assert_eq!(dealt_positions(&spec, 8, 3).unwrap()[..3], [3, 11, 19]);
+    assert_eq!(dealt_positions(&spec, 4, 4), Err(GameError::NoSuchPlayer(4)));
+    assert_eq!(dealt_positions(&spec, 1, 0), Err(GameError::PlayerCount(1)));
+}
+
+#[test]
+fn test_claims_and_challenges() {
+    let mut game = Game::new(3, RuleSet::bluff()).unwrap();
+    game.deal().unwrap();
+
+    assert_eq!(game.claim(1, 7, 2), Err(GameError::NotYourTurn(1)));
+    assert_eq!(game.claim(0, 14, 2), Err(GameError::InvalidRank(14)));
+    assert_eq!(game.claim(0, 7, 5), Err(GameError::InvalidQty(5)));
+    assert_eq!(game.claim(0, 7, 2), Ok(vec![RequiredProof::Play { player: 0 }]));
+    assert_eq!(game.hand_size(0), Ok(16));
+
+    // Unchallenged, the pile keeps its rank
+    assert_eq!(game.accept(), Ok(vec![]));
+    assert_eq!(game.claim(1, 8, 1), Err(GameError::InvalidRank(8)));
+    game.claim(1, 7, 3).unwrap();
+    assert_eq!(game.pile_size(), 5);
+
+    // A truthful claim, the challenger picks up the pile and the
+    // claimant plays again
+    assert_eq!(game.challenge(4), Err(GameError::NoSuchPlayer(4)));
+    assert_eq!(game.challenge(1), Err(GameError::OwnClaim(1)));
+    assert_eq!(game.challenge(2), Ok(vec![]));
+    assert_eq!(game.resolve(false), Ok(vec![RequiredProof::Claim { player: 1 },
+        RequiredProof::PickUp { player: 2 }]));
+    assert_eq!(game.hand_size(2), Ok(22));
+    assert_eq!(game.pile_size(), 0);
+    assert_eq!(game.phase(), Phase::Turn { player: 1 });
+
+    // A bluff, the claimant picks up and the challenger plays
+    game.claim(1, 2, 1).unwrap();
+    game.challenge(0).unwrap();
+    assert_eq!(game.resolve(true), Ok(vec![RequiredProof::Challenge { player: 1 },
+        RequiredProof::PickUp { player: 1 }]));
+    assert_eq!(game.hand_size(1), Ok(14));
+    assert_eq!(game.phase(), Phase::Turn { player: 0 });
+}
+
+#[test]
+fn test_win() {
+    let mut game = Game::new(2, RuleSet::bluff()).unwrap();
+    game.deal().unwrap();
+    game.hand_sizes = vec![2, 5];
+
+    // The last cards are challenged but truthful
+    game.claim(0, 9, 2).unwrap();
+    game.challenge(1).unwrap();
+    assert_eq!(game.resolve(false), Ok(vec![RequiredProof::Claim { player: 0 },
+        RequiredProof::PickUp { player: 1 }, RequiredProof::EmptyHand { player: 0 }]));
+    assert_eq!(game.phase(), Phase::Finished { winner: 0 });
+    assert_eq!(game.claim(1, 9, 1), Err(GameError::WrongPhase));
+
+    // Or not challenged at all
+    let mut game = Game::new(2, RuleSet::bluff()).unwrap();
+    game.deal().unwrap();
+    game.hand_sizes = vec![1, 5];
+    game.claim(0, 9, 1).unwrap();
+    assert_eq!(game.accept(), Ok(vec![RequiredProof::EmptyHand { player: 0 }]));
+}
+
+#[test]
+fn test_cheat() {
+    let mut game = Game::new(4, RuleSet::cheat()).unwrap();
+    game.deal().unwrap();
+
+    // Same or adjacent ranks, Ace and King are adjacent
+    game.claim(0, 1, 1).unwrap();
+    game.accept().unwrap();
+    assert_eq!(game.claim(1, 3, 1), Err(GameError::InvalidRank(3)));
+    assert_eq!(game.claim(1, 13, 2), Ok(vec![RequiredProof::Play { player: 1 }]));
+    game.accept().unwrap();
+    game.claim(2, 12, 1).unwrap();
+    game.accept().unwrap();
+
+    // Four of a kind discards
+    assert_eq!(game.discard(3, 5), Ok(vec![RequiredProof::Discard { player: 3 }]));
+    assert_eq!(game.hand_size(3), Ok(9));
+    assert_eq!(game.phase(), Phase::Turn { player: 3 });
+    game.claim(3, 12, 4).unwrap();
+    assert_eq!(game.pile_size(), 8);
+}
+
+#[test]
+fn test_i_doubt_it() {
+    // A game needs a deck
+    let rules = RuleSet { decks: 0, ..RuleSet::i_doubt_it() };
+    assert_eq!(Game::new(6, rules), Err(GameError::InvalidDeck));
+
+    // Two decks are dealt whole and claims go up to 8 cards
+    let mut game = Game::new(6, RuleSet { decks: 2, ..RuleSet::i_doubt_it() }).unwrap();
+    game.deal().unwrap();
+    assert_eq!(game.spec().size(), 2 * DECK_SIZE);
+    assert_eq!((0..6).map(|p| game.hand_size(p).unwrap()).sum::<usize>(), 2 * DECK_SIZE);
+    assert_eq!(game.claim(0, 1, 9), Err(GameError::InvalidQty(9)));
+    assert_eq!(game.claim(0, 1, 8), Ok(vec![RequiredProof::Play { player: 0 }]));
+
+    let mut game = Game::new(6, RuleSet::i_doubt_it()).unwrap();
+    game.deal().unwrap();
+    assert_eq!(game.rules().max_claim().unwrap(), 4);
+    assert_eq!(game.hand_size(6), Err(GameError::NoSuchPlayer(6)));
+
+    // Aces first, then up the ranks whatever happens to the pile
+    assert_eq!(game.claim(0, 2, 1), Err(GameError::InvalidRank(2)));
+    assert_eq!(game.claim(0, 1, 5), Err(GameError::InvalidQty(5)));
+    game.claim(0, 1, 4).unwrap();
+    assert_eq!(game.challenge(1), Ok(vec![]));
+    assert_eq!(game.resolve(true).unwrap()[0], RequiredProof::Challenge { player: 0 });
+    assert_eq!(game.phase(), Phase::Turn { player: 1 });
+    assert_eq!(game.claim(1, 1, 1), Err(GameError::InvalidRank(1)));
+    game.claim(1, 2, 1).unwrap();
+    game.accept().unwrap();
+
+    assert_eq!(game.discard(2, 3), Err(GameError::NotAllowed));
+    assert_eq!(next_rank(13), 1);
+}
+
+#[test]
+fn test_wild_jokers() {
+    // Two decks and their four jokers are dealt
+    let rules = RuleSet { decks: 2, jokers_wild: true, ..RuleSet::cheat() };
+    let mut game = Game::new(5, rules).unwrap();
+    assert_eq!(game.spec().jokers(), 2 * JOKERS_PER_DECK as u64);
+    game.deal().unwrap();
+    assert_eq!((0..5).map(|p| game.hand_size(p).unwrap()).sum::<usize>(),
+        2 * DECK_SIZE + 2 * JOKERS_PER_DECK);
+
+    // Jokers stand in for a rank, a claim still holds at most 8 cards
+    assert_eq!(game.rules().max_claim().unwrap(), 8);
+    assert_eq!(game.claim(0, 5, 9), Err(GameError::InvalidQty(9)));
+    game.claim(0, 5, 8).unwrap();
+}
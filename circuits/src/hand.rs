@@ -0,0 +1,1387 @@
+use std::marker::PhantomData;
+use halo2_proofs::{
+    circuit::{Layouter, Value, SimpleFloorPlanner},
+    plonk::{Advice, Instance, Assigned, Column, ConstraintSystem,
+        Constraints, Error, Expression, Selector, Circuit},
+    poly::Rotation,
+    pasta::Fp,
+};
+#[cfg(test)]
+use halo2_proofs::dev::MockProver;
+
+use crate::card::{configure_card, card_code, CardError, CODE_SUITE_STRIDE};
+use crate::claim::{PlayedChip, PlayedConfig, PlayedCard, played_cards,
+    create_claim_public_inputs, played_slots, claim_rows};
+use crate::deck::{DeckCard, DeckChip, DeckConfig, DeckSpec, card_witnesses, deck_depth, deck_path};
+#[cfg(test)]
+use crate::deck::{card_commitments, deck_root, salted, standard_deck};
+use crate::game::MIN_PLAYERS;
+#[cfg(test)]
+use crate::game::dealt_positions;
+use crate::permutation::{Cards, PermutationChip, PermutationConfig};
+use crate::poseidon::{self, configure_poseidon, PoseidonChip, PoseidonConfig, PoseidonField, Word};
+use crate::qty::{QtyChip, QtyConfig};
+use crate::rank::{RankChip, RankConfig};
+use crate::suite::{SuiteChip, SuiteConfig, SUITE_MIN, SUITE_MAX};
+
+// The hand commitment hashes the slots a chunk at a time, a suite's
+// worth of cards
+pub const HAND_CHUNK: usize = 13;
+
+// A hand has a slot per card of the deck, padded to whole chunks.
+// Empty slots hold 0.
+pub fn hand_slots(spec: &DeckSpec) -> usize {
+    spec.size().next_multiple_of(HAND_CHUNK)
+}
+
+// Rows of the hand commitments, after the claim rows
+pub fn old_hand_row(spec: &DeckSpec) -> usize {
+    claim_rows(spec)
+}
+
+pub fn new_hand_row(spec: &DeckSpec) -> usize {
+    old_hand_row(spec) + 1
+}
+
+// Rows of the public input column when disclosing a single hand
+pub const PLAYER_ROW: usize = 0;
+pub const SESSION_ROW: usize = 1;
+pub const HAND_ROW: usize = 2;
+pub const HAND_SIZE_ROW: usize = 3;
+
+// Rows of the public input column when discarding a four of a kind
+pub const DISCARD_RANK_ROW: usize = 2;
+pub const DISCARD_OLD_HAND_ROW: usize = 3;
+pub const DISCARD_NEW_HAND_ROW: usize = 4;
+
+// Rows of the public input column when dealing a hand, after the hand:
+// the deck root then an (active, position) pair per deal slot
+pub const DEAL_ROOT_ROW: usize = 3;
+pub const DEAL_SLOTS_ROW: usize = 4;
+
+// A hand is dealt at most its share of the deck among the fewest
+// players
+pub fn deal_slots(spec: &DeckSpec) -> usize {
+    spec.size().div_ceil(MIN_PLAYERS)
+}
+
+// A four of a kind holds one card of every suite. The suites are
+// fixed in the circuit, so the domain must be exactly 4 suites.
+pub const FOUR_OF_A_KIND: usize = 4;
+const _: () = assert!(SUITE_MAX - SUITE_MIN + 1 == FOUR_OF_A_KIND as u64);
+
+// Fewest cards in a hand, at most every card of the deck
+pub const HAND_SIZE_MIN: u64 = 0;
+
+// A player's hand in a game session played with the cards of `spec`.
+// The cards are kept in their slot order, so the same cards in another
+// order are another hand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hand {
+    pub spec: DeckSpec,
+    pub player: u64,
+    pub session: Fp,
+    pub salt: Fp,
+    pub cards: Vec<(u64, u64)>,
+}
+
+impl Hand {
+    // The card codes of the slots, padded with empty slots
+    pub fn codes(&self) -> Result<Vec<u64>, CardError> {
+        if self.cards.len() > self.spec.size() {
+            return Err(CardError::HandSize(self.cards.len()));
+        }
+
+        let mut codes: Vec<u64> = self.cards.iter()
+            .map(|(suite, rank)| card_code(*suite, *rank)).collect();
+        codes.resize(hand_slots(&self.spec), 0);
+        Ok(codes)
+    }
+
+    // Commits to the hand, bound to the player and the session:
+    // H(player, session, salt) chained with each chunk of slots
+    pub fn commitment(&self) -> Result<Fp, CardError> {
+        let owner = poseidon::hash([Fp::from(self.player), self.session, self.salt]);
+
+        Ok(self.codes()?.chunks(HAND_CHUNK).fold(owner, |commitment, chunk| {
+            let mut inputs = [Fp::zero(); 1 + HAND_CHUNK];
+            inputs[0] = commitment;
+            for (input, code) in inputs[1..].iter_mut().zip(chunk) {
+                *input = Fp::from(*code);
+            }
+
+            poseidon::hash(inputs)
+        }))
+    }
+
+    // Checks the other hand is of the same player in the same session
+    pub(crate) fn check_owner(&self, other: &Hand) -> Result<(), CardError> {
+        if (self.spec, self.player, self.session) !=
+            (other.spec, other.player, other.session) {
+            return Err(CardError::OwnerMismatch);
+        }
+        Ok(())
+    }
+
+    // The hand left after playing the cards, under a fresh salt. None
+    // if one of them is not in the hand.
+    pub fn without(&self, played: &[(u64, u64)], salt: Fp) -> Option<Hand> {
+        let mut cards = self.cards.clone();
+        for card in played {
+            let i = cards.iter().position(|c| c == card)?;
+            cards.remove(i);
+        }
+
+        Some(Hand { salt, cards, ..self.clone() })
+    }
+
+    pub(crate) fn witness(&self) -> Result<HandCards<Fp>, CardError> {
+        let slots = self.codes()?.into_iter()
+            .map(|code| Value::known(Fp::from(code).into()))
+            .collect();
+
+        Ok(HandCards { salt: Value::known(self.salt.into()), slots })
+    }
+}
+
+// The private content of a committed hand
+#[derive(Debug, Clone)]
+pub struct HandCards<F: PoseidonField> {
+    salt: Value<Assigned<F>>,
+    slots: Vec<Value<Assigned<F>>>,
+}
+
+impl<F: PoseidonField> HandCards<F> {
+    // A hand of the spec without witnesses
+    pub fn empty(spec: &DeckSpec) -> Self {
+        Self {
+            salt: Value::unknown(),
+            slots: vec![Value::unknown(); hand_slots(spec)],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HandConfig<F: PoseidonField> {
+    poseidon: PoseidonConfig<F>,
+    permutation: PermutationConfig,
+    hand: Column<Advice>,
+    active: Column<Advice>,
+    suite: Column<Advice>,
+    rank: Column<Advice>,
+    code: Column<Advice>,
+    s_code: Selector,
+}
+
+// Commits hands and compares their cards by code. Slot codes are not
+// range checked, every card in a hand was checked by the deal or the
+// pick up proof that put it there.
+#[derive(Debug, Clone)]
+pub(crate) struct HandChip<F: PoseidonField> {
+    config: HandConfig<F>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> HandChip<F> {
+
+    pub fn construct(config: HandConfig<F>) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>,
+                poseidon: PoseidonConfig<F>) -> HandConfig<F> {
+
+        let permutation = PermutationChip::configure(meta);
+        let hand = meta.advice_column();
+        let active = meta.advice_column();
+        let suite = meta.advice_column();
+        let rank = meta.advice_column();
+        let code = meta.advice_column();
+        let s_code = meta.selector();
+
+        for column in [hand, active, suite, rank, code] {
+            meta.enable_equality(column);
+        }
+
+        let stride = Expression::Constant(F::from(CODE_SUITE_STRIDE));
+
+        // | active | suite | rank | code | selector |
+        // |--------|-------|------|------|----------|
+        // |   a    |   s   |  r   |  c   |  s_code  |
+        //
+        // c = a * (s * 16 + r), the code of a played card or 0
+        meta.create_gate("hand card code", |meta| {
+            let s = meta.query_selector(s_code);
+            let a = meta.query_advice(active, Rotation::cur());
+            let suite = meta.query_advice(suite, Rotation::cur());
+            let r = meta.query_advice(rank, Rotation::cur());
+            let c = meta.query_advice(code, Rotation::cur());
+
+            Constraints::with_selector(s, Some(("code", c - a * (suite * stride.clone() + r))))
+        });
+
+        HandConfig {
+            poseidon,
+            permutation,
+            hand,
+            active,
+            suite,
+            rank,
+            code,
+            s_code,
+        }
+    }
+
+    // Assigns the owner of the hands, the player and the session
+    pub fn assign_owner(&self, mut layouter: impl Layouter<F>,
+        player: Value<Assigned<F>>, session: Value<Assigned<F>>)
+        -> Result<(Word<F>, Word<F>), Error> {
+
+        layouter.assign_region(|| "Hand owner", |mut region| {
+            Ok((
+                region.assign_advice(|| "player", self.config.hand, 0, || player)?,
+                region.assign_advice(|| "session", self.config.hand, 1, || session)?,
+            ))
+        })
+    }
+
+    // Commits to the hand of the owner. Returns the commitment and the
+    // slot codes.
+    pub fn commit(&self, mut layouter: impl Layouter<F>, owner: &(Word<F>, Word<F>),
+        hand: &HandCards<F>) -> Result<(Word<F>, Vec<Word<F>>), Error> {
+
+        let (salt, slots) = layouter.assign_region(|| "Hand", |mut region| {
+            let salt = region.assign_advice(|| "salt", self.config.hand, 0, || hand.salt)?;
+            let slots = hand.slots.iter().enumerate().map(|(i, slot)| {
+                region.assign_advice(|| "slot", self.config.hand, i + 1, || *slot)
+            }).collect::<Result<Vec<_>, _>>()?;
+
+            Ok((salt, slots))
+        })?;
+
+        let mut commitment = self.poseidon().hash(layouter.namespace(|| "Hand owner"),
+            &[owner.0.clone(), owner.1.clone(), salt])?;
+        for chunk in slots.chunks(HAND_CHUNK) {
+            let mut inputs = vec![commitment];
+            inputs.extend(chunk.iter().cloned());
+            let inputs: [_; 1 + HAND_CHUNK] = inputs.try_into()
+                .map_err(|_| Error::Synthesis)?;
+            commitment = self.poseidon().hash(
+                layouter.namespace(|| "Hand commitment"), &inputs)?;
+        }
+
+        Ok((commitment, slots))
+    }
+
+    // Codes of the played cards, 0 for the inactive slots
+    pub fn played_codes(&self, mut layouter: impl Layouter<F>, active: &[Word<F>],
+        suite: &[Word<F>], rank: &[Word<F>]) -> Result<Vec<Word<F>>, Error> {
+
+        let stride = Value::known(Assigned::from(F::from(CODE_SUITE_STRIDE)));
+
+        layouter.assign_region(|| "Played codes", |mut region| {
+            active.iter().zip(suite).zip(rank).enumerate()
+                .map(|(offset, ((a, s), r))| {
+                self.config.s_code.enable(&mut region, offset)?;
+
+                let a = a.copy_advice(|| "active", &mut region, self.config.active, offset)?;
+                let s = s.copy_advice(|| "suite", &mut region, self.config.suite, offset)?;
+                let r = r.copy_advice(|| "rank", &mut region, self.config.rank, offset)?;
+
+                region.assign_advice(|| "code", self.config.code, offset,
+                    || a.value().copied() *
+                        (s.value().copied() * stride + r.value().copied()))
+            }).collect()
+        })
+    }
+
+    // `n` empty slots
+    pub fn empty(&self, mut layouter: impl Layouter<F>, n: usize)
+        -> Result<Vec<Word<F>>, Error> {
+
+        layouter.assign_region(|| "Empty slots", |mut region| {
+            (0..n).map(|offset| {
+                region.assign_advice_from_constant(|| "empty", self.config.hand,
+                    offset, Assigned::from(F::ZERO))
+            }).collect()
+        })
+    }
+
+    pub fn poseidon(&self) -> PoseidonChip<F> {
+        PoseidonChip::construct(self.config.poseidon.clone())
+    }
+
+    pub fn permutation(&self) -> PermutationChip<F> {
+        PermutationChip::construct(self.config.permutation.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HandSizeConfig {
+    slot: Column<Advice>,
+    inverse: Column<Advice>,
+    held: Column<Advice>,
+    count: Column<Advice>,
+    range: QtyConfig,
+    s_slot: Selector,
+    s_first: Selector,
+    s_next: Selector,
+}
+
+// Counts the cards held in the slots of a hand, range checked by a
+// quantity chip over the hand sizes
+#[derive(Debug, Clone)]
+pub(crate) struct HandSizeChip<F: PoseidonField> {
+    config: HandSizeConfig,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> HandSizeChip<F> {
+
+    pub fn construct(config: HandSizeConfig) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>) -> HandSizeConfig {
+
+        let slot = meta.advice_column();
+        let inverse = meta.advice_column();
+        let held = meta.advice_column();
+        let count = meta.advice_column();
+        let q_range_check = meta.complex_selector();
+        let s_slot = meta.selector();
+        let s_first = meta.selector();
+        let s_next = meta.selector();
+
+        for column in [slot, count] {
+            meta.enable_equality(column);
+        }
+
+        let one = Expression::Constant(F::ONE);
+
+        // | slot | inverse | held | count | selector        |
+        // |------|---------|------|-------|-----------------|
+        // |  c0  |  1/c0   |  h0  |  h0   | s_slot, s_first |
+        // |  c1  |  1/c1   |  h1  |  +h1  | s_slot, s_next  |
+        //
+        // h = 1 iff the slot holds a card (c != 0), the count adds
+        // them up
+        meta.create_gate("hand size slot", |meta| {
+            let s = meta.query_selector(s_slot);
+            let c = meta.query_advice(slot, Rotation::cur());
+            let inv = meta.query_advice(inverse, Rotation::cur());
+            let h = meta.query_advice(held, Rotation::cur());
+
+            Constraints::with_selector(s, [
+                ("held", h.clone() - c.clone() * inv),
+                ("empty", c * (one.clone() - h)),
+            ])
+        });
+
+        meta.create_gate("hand size first slot", |meta| {
+            let s = meta.query_selector(s_first);
+            let h = meta.query_advice(held, Rotation::cur());
+            let n = meta.query_advice(count, Rotation::cur());
+
+            Constraints::with_selector(s, Some(("count", n - h)))
+        });
+
+        meta.create_gate("hand size next slot", |meta| {
+            let s = meta.query_selector(s_next);
+            let h = meta.query_advice(held, Rotation::cur());
+            let n_prev = meta.query_advice(count, Rotation::prev());
+            let n = meta.query_advice(count, Rotation::cur());
+
+            Constraints::with_selector(s, Some(("count", n - n_prev - h)))
+        });
+
+        // The final count is in [0, cards of the deck]
+        let range = QtyChip::configure_range(meta, count, q_range_check, HAND_SIZE_MIN);
+
+        HandSizeConfig {
+            slot,
+            inverse,
+            held,
+            count,
+            range,
+            s_slot,
+            s_first,
+            s_next,
+        }
+    }
+
+    fn range(&self) -> QtyChip<F> {
+        QtyChip::construct(self.config.range.clone())
+    }
+
+    // Loads the legal hand sizes of the spec into the lookup table
+    pub fn load_table(&self, layouter: impl Layouter<F>, spec: &DeckSpec)
+        -> Result<(), Error> {
+
+        self.range().load_range(layouter, HAND_SIZE_MIN..=spec.size() as u64)
+    }
+
+    // Counts the cards in the slots. Returns the range checked count.
+    pub fn assign(&self, mut layouter: impl Layouter<F>, slots: &[Word<F>])
+        -> Result<Word<F>, Error> {
+
+        layouter.assign_region(|| "Hand size", |mut region| {
+            let mut running = Value::known(Assigned::from(F::ZERO));
+            let mut count = None;
+
+            for (offset, slot) in slots.iter().enumerate() {
+                self.config.s_slot.enable(&mut region, offset)?;
+                if offset == 0 {
+                    self.config.s_first.enable(&mut region, offset)?;
+                } else {
+                    self.config.s_next.enable(&mut region, offset)?;
+                }
+
+                let c = slot.copy_advice(|| "slot", &mut region, self.config.slot, offset)?;
+                region.assign_advice(|| "inverse", self.config.inverse, offset,
+                    || c.value().map(|c| c.invert()))?;
+
+                let h = c.value().map(|c| if c.is_zero_vartime() {
+                    Assigned::from(F::ZERO)
+                } else {
+                    Assigned::from(F::ONE)
+                });
+                region.assign_advice(|| "held", self.config.held, offset, || h)?;
+
+                running = running + h;
+                count = Some(if offset + 1 == slots.len() {
+                    self.range().assign_in_region(&mut region, offset, running)?.0
+                } else {
+                    region.assign_advice(|| "count", self.config.count, offset,
+                        || running)?
+                });
+            }
+
+            count.ok_or(Error::Synthesis)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayConfig<F: PoseidonField> {
+    played: PlayedConfig<F>,
+    hand: HandConfig<F>,
+    instance: Column<Instance>,
+}
+
+// Proves that the committed played cards of a claim came out of the
+// player's hand: the old hand holds the new hand and the played
+// cards, so the hand shrinks by exactly the played count. The
+// played cards need not match the claim.
+pub struct PlayCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    player: Value<Assigned<F>>,
+    session: Value<Assigned<F>>,
+    old: HandCards<F>,
+    new: HandCards<F>,
+    claimed_rank: Value<Assigned<F>>,
+    count: Value<Assigned<F>>,
+    cards: Vec<PlayedCard<F>>,
+}
+
+impl<F: PoseidonField> PlayCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            player: Value::unknown(),
+            session: Value::unknown(),
+            old: HandCards::empty(&spec),
+            new: HandCards::empty(&spec),
+            claimed_rank: Value::unknown(),
+            count: Value::unknown(),
+            cards: vec![PlayedCard::default(); played_slots(&spec)],
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for PlayCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for PlayCircuit<F> {
+
+    type Config = PlayConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let card = configure_card(meta, instance);
+        let hand = HandChip::configure(meta, card.poseidon().clone());
+        let played = PlayedChip::configure(meta, card, instance);
+
+        PlayConfig { played, hand, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let played = PlayedChip::construct(config.played);
+        let hand: HandChip<F> = HandChip::construct(config.hand);
+        played.load_tables(layouter.namespace(|| "Card Tables"), &self.spec)?;
+
+        let slots = played.assign(layouter.namespace(|| "Played"),
+            self.claimed_rank, self.count, &self.cards)?;
+
+        let owner = hand.assign_owner(layouter.namespace(|| "Owner"),
+            self.player, self.session)?;
+        let (old, old_slots) = hand.commit(layouter.namespace(|| "Old hand"),
+            &owner, &self.old)?;
+        let (new, new_slots) = hand.commit(layouter.namespace(|| "New hand"),
+            &owner, &self.new)?;
+
+        // old + empty slots = new + played, the played cards leave
+        // empty slots behind
+        let mut left = old_slots;
+        left.extend(hand.empty(layouter.namespace(|| "Empty"), self.cards.len())?);
+        let mut right = new_slots;
+        right.extend(hand.played_codes(layouter.namespace(|| "Played codes"),
+            &slots.active, &slots.suite, &slots.rank)?);
+
+        // The challenge is bound to both hands and the played cards,
+        // chained one exposed commitment at a time
+        let mut challenge = hand.poseidon().hash(
+            layouter.namespace(|| "Play challenge"), &[old.clone(), new.clone()])?;
+        for exposed in &slots.exposed {
+            challenge = hand.poseidon().hash(
+                layouter.namespace(|| "Play challenge"), &[challenge, exposed.clone()])?;
+        }
+
+        hand.permutation().assign(layouter.namespace(|| "Play check"),
+            &challenge, Cards::Codes(&left), Cards::Codes(&right))?;
+
+        layouter.constrain_instance(old.cell(), config.instance, old_hand_row(&self.spec))?;
+        layouter.constrain_instance(new.cell(), config.instance, new_hand_row(&self.spec))
+    }
+}
+
+// Creates the play circuit for the cards (suite, rank, salt) going
+// from the old to the new hand
+pub fn create_play_circuit(old: &Hand, new: &Hand, claimed_rank: u64,
+    cards: &[(u64, u64, Fp)]) -> Result<PlayCircuit<Fp>, CardError> {
+
+    old.check_owner(new)?;
+
+    Ok(PlayCircuit {
+        spec: old.spec,
+        player: Value::known(Fp::from(old.player).into()),
+        session: Value::known(old.session.into()),
+        old: old.witness()?,
+        new: new.witness()?,
+        claimed_rank: Value::known(Fp::from(claimed_rank).into()),
+        count: Value::known(Fp::from(cards.len() as u64).into()),
+        cards: played_cards(&old.spec, claimed_rank, cards)?,
+    })
+}
+
+// Formats the public inputs, the claim followed by the old and the
+// new hand commitments
+pub fn create_play_public_inputs(old: &Hand, new: &Hand, claimed_rank: u64,
+    commitments: &[Fp]) -> Result<Vec<Fp>, CardError> {
+
+    old.check_owner(new)?;
+
+    let mut public_inputs = create_claim_public_inputs(&old.spec, claimed_rank, commitments)?;
+    public_inputs.push(old.commitment()?);
+    public_inputs.push(new.commitment()?);
+
+    Ok(public_inputs)
+}
+
+#[derive(Debug, Clone)]
+pub struct EmptyHandConfig<F: PoseidonField> {
+    hand: HandConfig<F>,
+    instance: Column<Instance>,
+}
+
+// Proves that the committed hand of the player in the session holds
+// no card, i.e the player has won
+pub struct EmptyHandCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    player: Value<Assigned<F>>,
+    session: Value<Assigned<F>>,
+    hand: HandCards<F>,
+}
+
+impl<F: PoseidonField> EmptyHandCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            player: Value::unknown(),
+            session: Value::unknown(),
+            hand: HandCards::empty(&spec),
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for EmptyHandCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for EmptyHandCircuit<F> {
+
+    type Config = EmptyHandConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let poseidon = configure_poseidon(meta);
+        let hand = HandChip::configure(meta, poseidon);
+        meta.enable_equality(instance);
+
+        EmptyHandConfig { hand, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let hand: HandChip<F> = HandChip::construct(config.hand);
+
+        let owner = hand.assign_owner(layouter.namespace(|| "Owner"),
+            self.player, self.session)?;
+        let (commitment, slots) = hand.commit(layouter.namespace(|| "Hand"),
+            &owner, &self.hand)?;
+
+        // Every slot is empty
+        layouter.assign_region(|| "Empty hand", |mut region| {
+            for slot in &slots {
+                region.constrain_constant(slot.cell(), F::ZERO)?;
+            }
+            Ok(())
+        })?;
+
+        layouter.constrain_instance(owner.0.cell(), config.instance, PLAYER_ROW)?;
+        layouter.constrain_instance(owner.1.cell(), config.instance, SESSION_ROW)?;
+        layouter.constrain_instance(commitment.cell(), config.instance, HAND_ROW)
+    }
+}
+
+pub fn create_empty_hand_circuit(hand: &Hand) -> Result<EmptyHandCircuit<Fp>, CardError> {
+    Ok(EmptyHandCircuit {
+        spec: hand.spec,
+        player: Value::known(Fp::from(hand.player).into()),
+        session: Value::known(hand.session.into()),
+        hand: hand.witness()?,
+    })
+}
+
+// Formats the public inputs, the player, the session and the hand
+// commitment
+pub fn create_hand_public_inputs(player: u64, session: Fp, commitment: Fp) -> Vec<Fp> {
+    vec![Fp::from(player), session, commitment]
+}
+
+#[derive(Debug, Clone)]
+pub struct HandSizeCircuitConfig<F: PoseidonField> {
+    hand: HandConfig<F>,
+    size: HandSizeConfig,
+    instance: Column<Instance>,
+}
+
+// Proves that the committed hand of the player in the session holds
+// exactly the public number of cards, without revealing them
+pub struct HandSizeCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    player: Value<Assigned<F>>,
+    session: Value<Assigned<F>>,
+    hand: HandCards<F>,
+}
+
+impl<F: PoseidonField> HandSizeCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            player: Value::unknown(),
+            session: Value::unknown(),
+            hand: HandCards::empty(&spec),
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for HandSizeCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for HandSizeCircuit<F> {
+
+    type Config = HandSizeCircuitConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let poseidon = configure_poseidon(meta);
+        let hand = HandChip::configure(meta, poseidon);
+        let size = HandSizeChip::configure(meta);
+        meta.enable_equality(instance);
+
+        HandSizeCircuitConfig { hand, size, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let hand: HandChip<F> = HandChip::construct(config.hand);
+        let size: HandSizeChip<F> = HandSizeChip::construct(config.size);
+        size.load_table(layouter.namespace(|| "Hand Size Table"), &self.spec)?;
+
+        let owner = hand.assign_owner(layouter.namespace(|| "Owner"),
+            self.player, self.session)?;
+        let (commitment, slots) = hand.commit(layouter.namespace(|| "Hand"),
+            &owner, &self.hand)?;
+        let count = size.assign(layouter.namespace(|| "Hand size"), &slots)?;
+
+        layouter.constrain_instance(owner.0.cell(), config.instance, PLAYER_ROW)?;
+        layouter.constrain_instance(owner.1.cell(), config.instance, SESSION_ROW)?;
+        layouter.constrain_instance(commitment.cell(), config.instance, HAND_ROW)?;
+        layouter.constrain_instance(count.cell(), config.instance, HAND_SIZE_ROW)
+    }
+}
+
+pub fn create_hand_size_circuit(hand: &Hand) -> Result<HandSizeCircuit<Fp>, CardError> {
+    Ok(HandSizeCircuit {
+        spec: hand.spec,
+        player: Value::known(Fp::from(hand.player).into()),
+        session: Value::known(hand.session.into()),
+        hand: hand.witness()?,
+    })
+}
+
+// Formats the public inputs, the hand followed by its size
+pub fn create_hand_size_public_inputs(player: u64, session: Fp, commitment: Fp,
+    size: u64) -> Vec<Fp> {
+
+    let mut public_inputs = create_hand_public_inputs(player, session, commitment);
+    public_inputs.push(Fp::from(size));
+
+    public_inputs
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscardConfig<F: PoseidonField> {
+    hand: HandConfig<F>,
+    suite: SuiteConfig,
+    rank: RankConfig,
+    instance: Column<Instance>,
+}
+
+// Proves that the committed old hand of the player held the four
+// suites of the public rank and that the new hand is the old one
+// without them
+pub struct DiscardCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    player: Value<Assigned<F>>,
+    session: Value<Assigned<F>>,
+    rank: Value<Assigned<F>>,
+    old: HandCards<F>,
+    new: HandCards<F>,
+}
+
+impl<F: PoseidonField> DiscardCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            player: Value::unknown(),
+            session: Value::unknown(),
+            rank: Value::unknown(),
+            old: HandCards::empty(&spec),
+            new: HandCards::empty(&spec),
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for DiscardCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for DiscardCircuit<F> {
+
+    type Config = DiscardConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let poseidon = configure_poseidon(meta);
+        let hand = HandChip::configure(meta, poseidon);
+
+        let suite_column = meta.advice_column();
+        let rank_column = meta.advice_column();
+        meta.enable_equality(suite_column);
+        meta.enable_equality(rank_column);
+        meta.enable_equality(instance);
+
+        let q_suite = meta.complex_selector();
+        let suite = SuiteChip::configure(meta, suite_column, q_suite);
+        let q_rank = meta.complex_selector();
+        let rank = RankChip::configure(meta, rank_column, q_rank);
+
+        DiscardConfig { hand, suite, rank, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let hand: HandChip<F> = HandChip::construct(config.hand);
+        let suite: SuiteChip<F> = SuiteChip::construct(config.suite);
+        let rank: RankChip<F> = RankChip::construct(config.rank);
+        suite.load_table(layouter.namespace(|| "Suite Table"), &self.spec)?;
+        rank.load_table(layouter.namespace(|| "Rank Table"))?;
+
+        // A card of every suite, all of the same range checked rank
+        let (active, suites, ranks) = layouter.assign_region(|| "Four of a kind",
+            |mut region| {
+            let mut cells = (vec![], vec![], vec![]);
+            for (offset, s) in (SUITE_MIN..=SUITE_MAX).enumerate() {
+                cells.0.push(region.assign_advice_from_constant(|| "active",
+                    hand.config.active, offset, Assigned::from(F::ONE))?);
+
+                let s_cell = suite.assign_in_region(&mut region, offset,
+                    Value::known(Assigned::from(F::from(s))))?.0;
+                region.constrain_constant(s_cell.cell(), Assigned::from(F::from(s)))?;
+                cells.1.push(s_cell);
+
+                cells.2.push(rank.assign_in_region(&mut region, offset, self.rank)?.0);
+            }
+            Ok(cells)
+        })?;
+
+        let owner = hand.assign_owner(layouter.namespace(|| "Owner"),
+            self.player, self.session)?;
+        let (old, old_slots) = hand.commit(layouter.namespace(|| "Old hand"),
+            &owner, &self.old)?;
+        let (new, new_slots) = hand.commit(layouter.namespace(|| "New hand"),
+            &owner, &self.new)?;
+
+        // old + empty slots = new + discarded
+        let mut left = old_slots;
+        left.extend(hand.empty(layouter.namespace(|| "Empty"), FOUR_OF_A_KIND)?);
+        let mut right = new_slots;
+        right.extend(hand.played_codes(layouter.namespace(|| "Discarded codes"),
+            &active, &suites, &ranks)?);
+
+        let challenge = hand.poseidon().hash(
+            layouter.namespace(|| "Discard challenge"), &[old.clone(), new.clone()])?;
+        hand.permutation().assign(layouter.namespace(|| "Discard check"),
+            &challenge, Cards::Codes(&left), Cards::Codes(&right))?;
+
+        layouter.constrain_instance(owner.0.cell(), config.instance, PLAYER_ROW)?;
+        layouter.constrain_instance(owner.1.cell(), config.instance, SESSION_ROW)?;
+        for r in &ranks {
+            layouter.constrain_instance(r.cell(), config.instance, DISCARD_RANK_ROW)?;
+        }
+        layouter.constrain_instance(old.cell(), config.instance, DISCARD_OLD_HAND_ROW)?;
+        layouter.constrain_instance(new.cell(), config.instance, DISCARD_NEW_HAND_ROW)
+    }
+}
+
+// Creates the circuit discarding the four cards of `rank` from the
+// old hand
+pub fn create_discard_circuit(old: &Hand, new: &Hand, rank: u64)
+    -> Result<DiscardCircuit<Fp>, CardError> {
+
+    old.check_owner(new)?;
+
+    Ok(DiscardCircuit {
+        spec: old.spec,
+        player: Value::known(Fp::from(old.player).into()),
+        session: Value::known(old.session.into()),
+        rank: Value::known(Fp::from(rank).into()),
+        old: old.witness()?,
+        new: new.witness()?,
+    })
+}
+
+// Formats the public inputs, the owner, the rank then the old and the
+// new hand commitments
+pub fn create_discard_public_inputs(old: &Hand, new: &Hand, rank: u64)
+    -> Result<Vec<Fp>, CardError> {
+
+    old.check_owner(new)?;
+
+    Ok(vec![Fp::from(old.player), old.session, Fp::from(rank),
+        old.commitment()?, new.commitment()?])
+}
+
+#[derive(Debug, Clone)]
+pub struct DealConfig<F: PoseidonField> {
+    deck: DeckConfig<F>,
+    hand: HandConfig<F>,
+    instance: Column<Instance>,
+}
+
+// A card dealt to the player, with its path to the deck root. Unused
+// slots are inactive copies of a dealt card.
+#[derive(Debug, Clone)]
+pub struct DealtCard<F: PoseidonField> {
+    active: Value<Assigned<F>>,
+    card: DeckCard<F>,
+    position: Value<u64>,
+    path: Vec<Value<Assigned<F>>>,
+}
+
+impl<F: PoseidonField> DealtCard<F> {
+    fn empty(spec: &DeckSpec) -> Self {
+        Self {
+            active: Value::unknown(),
+            card: DeckCard::default(),
+            position: Value::unknown(),
+            path: vec![Value::unknown(); deck_depth(spec.size())],
+        }
+    }
+}
+
+// Proves that the first committed hand of the player in the session
+// holds the cards at the public positions of the deck root, in deal
+// order, and nothing else
+pub struct DealCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    player: Value<Assigned<F>>,
+    session: Value<Assigned<F>>,
+    hand: HandCards<F>,
+    cards: Vec<DealtCard<F>>,
+}
+
+impl<F: PoseidonField> DealCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            player: Value::unknown(),
+            session: Value::unknown(),
+            hand: HandCards::empty(&spec),
+            cards: vec![DealtCard::empty(&spec); deal_slots(&spec)],
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for DealCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for DealCircuit<F> {
+
+    type Config = DealConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let card = configure_card(meta, instance);
+        let hand = HandChip::configure(meta, card.poseidon().clone());
+        let deck = DeckChip::configure(meta, card, instance);
+
+        DealConfig { deck, hand, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let deck: DeckChip<F> = DeckChip::construct(config.deck);
+        let hand: HandChip<F> = HandChip::construct(config.hand);
+        deck.load_tables(layouter.namespace(|| "Card Tables"), &self.spec)?;
+
+        let owner = hand.assign_owner(layouter.namespace(|| "Owner"),
+            self.player, self.session)?;
+        let (commitment, slots) = hand.commit(layouter.namespace(|| "Hand"),
+            &owner, &self.hand)?;
+
+        // Every dealt card is a leaf of the deck root at its public
+        // position
+        let mut active = Vec::with_capacity(self.cards.len());
+        let mut suite = Vec::with_capacity(self.cards.len());
+        let mut rank = Vec::with_capacity(self.cards.len());
+        for (i, dealt) in self.cards.iter().enumerate() {
+            let (card, root, position) = deck.member(
+                layouter.namespace(|| format!("Dealt card {}", i)),
+                &dealt.card, dealt.position, &dealt.path)?;
+            let a = layouter.assign_region(|| "Dealt slot", |mut region| {
+                region.assign_advice(|| "active", hand.config.active, 0, || dealt.active)
+            })?;
+
+            deck.expose_public(layouter.namespace(|| "Deck root"), &root, DEAL_ROOT_ROW)?;
+            layouter.constrain_instance(a.cell(), config.instance, DEAL_SLOTS_ROW + 2 * i)?;
+            layouter.constrain_instance(position.cell(), config.instance,
+                DEAL_SLOTS_ROW + 2 * i + 1)?;
+
+            active.push(a);
+            suite.push(card.suite);
+            rank.push(card.rank);
+        }
+        let codes = hand.played_codes(layouter.namespace(|| "Dealt codes"),
+            &active, &suite, &rank)?;
+
+        // The hand holds the dealt cards in deal order, then empty slots
+        layouter.assign_region(|| "Dealt hand", |mut region| {
+            for (i, slot) in slots.iter().enumerate() {
+                match codes.get(i) {
+                    Some(code) => region.constrain_equal(slot.cell(), code.cell())?,
+                    None => region.constrain_constant(slot.cell(), F::ZERO)?,
+                }
+            }
+            Ok(())
+        })?;
+
+        layouter.constrain_instance(owner.0.cell(), config.instance, PLAYER_ROW)?;
+        layouter.constrain_instance(owner.1.cell(), config.instance, SESSION_ROW)?;
+        layouter.constrain_instance(commitment.cell(), config.instance, HAND_ROW)
+    }
+}
+
+// The (active, position) of a deal slot, unused slots repeat the
+// first dealt position
+fn deal_slot(positions: &[usize], slot: usize) -> (u64, usize) {
+    match positions.get(slot) {
+        Some(position) => (1, *position),
+        None => (0, positions[0]),
+    }
+}
+
+// Checks the hand of the spec is dealt 1 to `deal_slots` cards
+fn check_dealt_count(spec: &DeckSpec, count: usize) -> Result<(), CardError> {
+    if count == 0 || count > deal_slots(spec) {
+        return Err(CardError::HandSize(count));
+    }
+    Ok(())
+}
+
+// Creates the deal circuit of the hand from the deck commitments and
+// the dealt cards (position, suite, rank, salt), in deal order
+pub fn create_deal_circuit(hand: &Hand, commitments: &[Fp],
+    dealt: &[(usize, u64, u64, Fp)]) -> Result<DealCircuit<Fp>, CardError> {
+
+    let spec = hand.spec;
+    if commitments.len() != spec.size() {
+        return Err(CardError::DeckSize { expected: spec.size(), found: commitments.len() });
+    }
+    check_dealt_count(&spec, dealt.len())?;
+
+    let positions: Vec<usize> = dealt.iter().map(|(position, ..)| *position).collect();
+    let cards = (0..deal_slots(&spec)).map(|slot| {
+        let (active, position) = deal_slot(&positions, slot);
+        let (_, suite, rank, salt) = dealt[if active == 1 { slot } else { 0 }];
+        let card = card_witnesses(&[(suite, rank, salt)])[0];
+        let path = deck_path(commitments, position)?;
+
+        Ok(DealtCard {
+            active: Value::known(Fp::from(active).into()),
+            card,
+            position: Value::known(position as u64),
+            path: path.into_iter().map(|sibling| Value::known(sibling.into())).collect(),
+        })
+    }).collect::<Result<_, CardError>>()?;
+
+    Ok(DealCircuit {
+        spec,
+        player: Value::known(Fp::from(hand.player).into()),
+        session: Value::known(hand.session.into()),
+        hand: hand.witness()?,
+        cards,
+    })
+}
+
+// Formats the public inputs, the hand, the deck root then the
+// (active, position) of every deal slot
+pub fn create_deal_public_inputs(hand: &Hand, root: Fp, positions: &[usize])
+    -> Result<Vec<Fp>, CardError> {
+
+    check_dealt_count(&hand.spec, positions.len())?;
+
+    let mut public_inputs = create_hand_public_inputs(hand.player, hand.session,
+        hand.commitment()?);
+    public_inputs.push(root);
+    for slot in 0..deal_slots(&hand.spec) {
+        let (active, position) = deal_slot(positions, slot);
+        public_inputs.extend([Fp::from(active), Fp::from(position as u64)]);
+    }
+
+    Ok(public_inputs)
+}
+
+#[cfg(test)]
+fn dealt_hand() -> Hand {
+    Hand {
+        spec: DeckSpec::standard(),
+        player: 2,
+        session: Fp::from(0x5e55),
+        salt: Fp::from(77),
+        cards: vec![(1, 7), (2, 7), (4, 1), (3, 12), (1, 12), (2, 13)],
+    }
+}
+
+#[cfg(test)]
+fn run_play(old: &Hand, new: &Hand, claimed_rank: u64, cards: &[(u64, u64, Fp)])
+    -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 13;
+
+    let circuit = create_play_circuit(old, new, claimed_rank, cards).unwrap();
+    let public_inputs = create_play_public_inputs(old, new, claimed_rank,
+        &card_commitments(cards)).unwrap();
+    MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+}
+
+#[test]
+fn test_play_from_hand() {
+    let old = dealt_hand();
+    let cards = [(1, 7, Fp::from(31)), (2, 7, Fp::from(32)),
+                (1, 12, Fp::from(33)), (3, 12, Fp::from(34))];
+
+    for n in 1..=played_slots(&old.spec) {
+        let played: Vec<(u64, u64)> = cards[..n].iter().map(|(s, r, _)| (*s, *r)).collect();
+        let new = old.without(&played, Fp::from(78)).unwrap();
+        assert_eq!(new.cards.len(), old.cards.len() - n);
+
+        // The played cards need not match the claim
+        assert_eq!(run_play(&old, &new, 7, &cards[..n]), Ok(()));
+    }
+}
+
+#[cfg(test)]
+fn two_deck_sevens() -> Vec<(u64, u64)> {
+    (0..8).map(|i| (i % 4 + 1, 7)).collect()
+}
+
+#[test]
+fn test_play_two_decks() {
+    // Every 7 of two decks in a single claim
+    let sevens = two_deck_sevens();
+    let mut old = Hand { spec: DeckSpec::new(2, 0).unwrap(), ..dealt_hand() };
+    old.cards = [sevens.clone(), vec![(4, 1), (3, 12)]].concat();
+    let new = old.without(&sevens, Fp::from(78)).unwrap();
+
+    assert_eq!(run_play(&old, &new, 7, &salted(&sevens)), Ok(()));
+}
+
+#[test]
+fn test_invalid_hands() {
+    let old = dealt_hand();
+    let played = [(1, 7, Fp::from(31))];
+
+    // More cards than slots
+    let full = Hand { cards: vec![(1, 7); old.spec.size() + 1], ..dealt_hand() };
+    assert!(matches!(full.commitment(), Err(CardError::HandSize(53))));
+    assert!(create_hand_size_circuit(&full).is_err());
+
+    // The new hand of another player
+    let new = Hand { player: old.player + 1, ..old.without(&[(1, 7)], Fp::from(78)).unwrap() };
+    assert!(matches!(create_play_circuit(&old, &new, 7, &played),
+        Err(CardError::OwnerMismatch)));
+
+    // No card or more than a claim holds
+    let new = old.without(&[], Fp::from(78)).unwrap();
+    assert!(matches!(create_play_circuit(&old, &new, 7, &[]),
+        Err(CardError::PlayedCount(0))));
+    assert!(matches!(create_play_circuit(&old, &new, 7, &[played[0]; 5]),
+        Err(CardError::PlayedCount(5))));
+
+    // The new hand of a game with another deck
+    let new = Hand { spec: DeckSpec::new(2, 0).unwrap(), ..new };
+    assert!(matches!(create_play_circuit(&old, &new, 7, &played),
+        Err(CardError::OwnerMismatch)));
+}
+
+#[test]
+fn test_play_card_not_in_hand() {
+    let old = dealt_hand();
+
+    // A 5 that was never held, the hand drops a 7 instead
+    let new = old.without(&[(1, 7)], Fp::from(78)).unwrap();
+    assert!(run_play(&old, &new, 5, &[(1, 5, Fp::from(31))]).is_err());
+}
+
+#[test]
+fn test_play_keeps_hand_size() {
+    let old = dealt_hand();
+
+    // The played card stays in the new hand
+    let new = Hand { salt: Fp::from(78), ..old.clone() };
+    assert!(run_play(&old, &new, 7, &[(1, 7, Fp::from(31))]).is_err());
+
+    // Or a card is dropped on top of the played one
+    let new = old.without(&[(1, 7), (4, 1)], Fp::from(78)).unwrap();
+    assert!(run_play(&old, &new, 7, &[(1, 7, Fp::from(31))]).is_err());
+}
+
+#[test]
+fn test_empty_hand() {
+    const K: u32 = 12;
+
+    let mut hand = dealt_hand();
+    hand.cards.clear();
+    let circuit = create_empty_hand_circuit(&hand).unwrap();
+
+    let public_inputs = create_hand_public_inputs(hand.player, hand.session,
+        hand.commitment().unwrap());
+    let prover = MockProver::run(K, &circuit, vec![public_inputs]).unwrap();
+    assert_eq!(prover.verify(), Ok(()));
+
+    // Bound to the player and the session
+    for (player, session) in [(hand.player + 1, hand.session),
+            (hand.player, hand.session + Fp::from(1))] {
+        let public_inputs = create_hand_public_inputs(player, session,
+            hand.commitment().unwrap());
+        let prover = MockProver::run(K, &circuit, vec![public_inputs]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    // A hand still holding a card
+    let hand = dealt_hand().without(&[(1, 7), (2, 7), (4, 1), (3, 12), (1, 12)],
+        Fp::from(78)).unwrap();
+    let circuit = create_empty_hand_circuit(&hand).unwrap();
+    let public_inputs = create_hand_public_inputs(hand.player, hand.session,
+        hand.commitment().unwrap());
+    let prover = MockProver::run(K, &circuit, vec![public_inputs]).unwrap();
+    assert!(prover.verify().is_err());
+}
+
+#[test]
+fn test_hand_size() {
+    const K: u32 = 12;
+
+    let run = |hand: &Hand, size: u64| {
+        let circuit = create_hand_size_circuit(hand).unwrap();
+        let public_inputs = create_hand_size_public_inputs(hand.player,
+            hand.session, hand.commitment().unwrap(), size);
+        MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+    };
+
+    let hand = dealt_hand();
+    assert_eq!(run(&hand, 6), Ok(()));
+    assert!(run(&hand, 5).is_err());
+    assert!(run(&hand, 7).is_err());
+
+    let empty = Hand { cards: vec![], ..dealt_hand() };
+    assert_eq!(run(&empty, 0), Ok(()));
+
+    // Hands of two decks hold up to 104 cards
+    let spec = DeckSpec::new(2, 0).unwrap();
+    let both = Hand { spec, cards: two_deck_sevens(), ..dealt_hand() };
+    assert_eq!(run(&both, 8), Ok(()));
+    let full = Hand { spec, cards: spec.cards(), ..dealt_hand() };
+    assert_eq!(run(&full, 104), Ok(()));
+}
+
+#[test]
+fn test_discard_four_of_a_kind() {
+    const K: u32 = 13;
+
+    let run = |old: &Hand, new: &Hand, rank: u64| {
+        let circuit = create_discard_circuit(old, new, rank).unwrap();
+        let public_inputs = create_discard_public_inputs(old, new, rank).unwrap();
+        MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+    };
+
+    let mut old = dealt_hand();
+    old.cards.extend([(3, 7), (4, 7)]);
+    let kind: Vec<(u64, u64)> = (SUITE_MIN..=SUITE_MAX).map(|s| (s, 7)).collect();
+    let new = old.without(&kind, Fp::from(78)).unwrap();
+    assert_eq!(run(&old, &new, 7), Ok(()));
+
+    // Another rank is not discarded
+    assert!(run(&old, &new, 12).is_err());
+
+    // Three of a kind and another card
+    let mut old = dealt_hand();
+    old.cards.push((3, 7));
+    let new = old.without(&[(1, 7), (2, 7), (3, 7), (2, 13)], Fp::from(78)).unwrap();
+    assert!(run(&old, &new, 7).is_err());
+
+    // The discarded cards stay in the new hand
+    let new = Hand { salt: Fp::from(78), ..old.clone() };
+    assert!(run(&old, &new, 7).is_err());
+}
+
+#[cfg(test)]
+fn run_deal(hand: &Hand, commitments: &[Fp], dealt: &[(usize, u64, u64, Fp)], root: Fp,
+    positions: &[usize]) -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 14;
+
+    let circuit = create_deal_circuit(hand, commitments, dealt).unwrap();
+    let public_inputs = create_deal_public_inputs(hand, root, positions).unwrap();
+    MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+}
+
+#[test]
+fn test_deal() {
+    let mut cards = standard_deck();
+    cards.reverse();
+    let deck = salted(&cards);
+    let commitments = card_commitments(&deck);
+    let root = deck_root(&commitments).unwrap();
+
+    // The second of four players is dealt every fourth card
+    let positions = dealt_positions(&DeckSpec::standard(), 4, 1).unwrap();
+    let dealt: Vec<(usize, u64, u64, Fp)> = positions.iter()
+        .map(|p| (*p, deck[*p].0, deck[*p].1, deck[*p].2))
+        .collect();
+    let hand = Hand {
+        cards: dealt.iter().map(|(_, suite, rank, _)| (*suite, *rank)).collect(),
+        ..dealt_hand()
+    };
+    assert_eq!(run_deal(&hand, &commitments, &dealt, root, &positions), Ok(()));
+
+    // The cards of another player
+    let other = dealt_positions(&DeckSpec::standard(), 4, 2).unwrap();
+    assert!(run_deal(&hand, &commitments, &dealt, root, &other).is_err());
+
+    // A hand with a card swapped for one that was not dealt
+    let mut swapped = hand.clone();
+    swapped.cards[3] = cards[0];
+    assert!(run_deal(&swapped, &commitments, &dealt, root, &positions).is_err());
+
+    // Or holding the dealt cards out of deal order
+    let mut reordered = hand.clone();
+    reordered.cards.swap(0, 1);
+    assert!(run_deal(&reordered, &commitments, &dealt, root, &positions).is_err());
+
+    // A dealt card opened with another salt
+    let mut resalted = dealt.clone();
+    resalted[0].3 += Fp::from(1);
+    assert!(run_deal(&hand, &commitments, &resalted, root, &positions).is_err());
+
+    // Dealt under another deck root
+    assert!(run_deal(&hand, &commitments, &dealt, root + Fp::from(1), &positions).is_err());
+
+    // Fewer or more cards than a hand is dealt
+    assert!(matches!(create_deal_circuit(&hand, &commitments, &[]),
+        Err(CardError::HandSize(0))));
+    assert!(matches!(create_deal_public_inputs(&hand, root, &[0; 27]),
+        Err(CardError::HandSize(27))));
+}
@@ -1,3 +1,19 @@
 
 pub mod card;
+pub mod challenge;
+pub mod deck;
+pub mod dkg;
+pub mod envelope;
+pub mod game;
+pub mod hand;
+pub mod mental_poker;
+pub mod merkle;
+pub mod claim;
+pub mod permutation;
+pub mod pile;
+pub mod poseidon;
+pub mod qty;
+pub mod rank;
+pub mod shuffle;
+pub mod suite;
 pub mod wasm;
\ No newline at end of file
@@ -1,41 +1,282 @@
 #[cfg(not(target_family = "wasm"))]
+use std::{env, error::Error, fs, io::BufReader, process::ExitCode};
+use halo2_proofs::{
+    pasta::{Fp, EqAffine},
+    plonk::keygen_vk,
+    poly::commitment::Params,
+};
+use ff::{Field, PrimeField};
+use group::GroupEncoding;
+use rand_core::OsRng;
+use card::card::{empty_circuit, create_circuit, generate_setup_params,
+                generate_keys, run_mock_prover, generate_proof, verify,
+                create_public_inputs, card_vk_fingerprint, check_vk, CardError};
+use card::envelope::{EnvelopeError, ProofEnvelope, ENVELOPE_MAGIC, to_hex, from_hex};
+use card::dkg::{KeyShare, TableKey};
+use card::game::{MIN_PLAYERS, MAX_PLAYERS};
 
-use halo2_proofs::pasta::{Fp};
+const USAGE: &str = "usage:
+    circuits setup --k <k> --out <params>
+    circuits prove --card <card> [--qty <qty>] [--salt <hex>] --params <params> --out <proof>
+        [--format bin|json]
+    circuits verify --params <params> --commitment <hex> --proof <proof> [--vk <hex>]
+    circuits inspect [--params <params>] [--proof <proof>]
+    circuits dkg [--players <players>] [--session <session>]
 
-fn main() {
-    use card::card::{empty_circuit, create_circuit, generate_setup_params,
-                    generate_keys, run_mock_prover, generate_proof, verify,
-                    create_public_inputs};
+cards are a rank A, 2-10, J, Q, K followed by a suite H, D, S, F (or C),
+e.g. QH or 10S";
 
-    // Size of the circuit
-    let k = 5;
+type CliResult<T> = Result<T, Box<dyn Error>>;
 
-    // Private input to generate a proof with
-    let qty = 3 as u64;
-    let suite = 3 as u64;
-    let rank = 11 as u64;
-    let public_inputs = create_public_inputs(qty, suite, rank);
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    // Create the circuit
-    let card_circuit = create_circuit(qty, suite, rank);
+fn run() -> CliResult<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, flags) = match args.split_first() {
+        Some((command, flags)) => (command.as_str(), flags),
+        None => return Err(USAGE.into()),
+    };
+    let flags = Flags::parse(flags)?;
 
-    // Run mock prover    
-    run_mock_prover(k, &card_circuit, (&public_inputs.0,
-                        &public_inputs.1, &public_inputs.2));
+    match command {
+        "setup" => setup(&flags),
+        "prove" => prove(&flags),
+        "verify" => verify_proof(&flags),
+        "inspect" => inspect(&flags),
+        "dkg" => dkg(&flags),
+        _ => Err(format!("unknown command {}\n{}", command, USAGE).into()),
+    }
+}
+
+// The `--name value` pairs following the command
+struct Flags(Vec<(String, String)>);
+
+impl Flags {
+    fn parse(args: &[String]) -> CliResult<Self> {
+        let mut flags = vec![];
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let name = arg.strip_prefix("--")
+                .ok_or_else(|| format!("unexpected argument {}\n{}", arg, USAGE))?;
+            let value = args.next()
+                .ok_or_else(|| format!("missing value of --{}", name))?;
+            flags.push((name.to_string(), value.clone()));
+        }
+
+        Ok(Flags(flags))
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(flag, _)| flag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn required(&self, name: &str) -> CliResult<&str> {
+        self.get(name).ok_or_else(|| format!("missing --{}\n{}", name, USAGE).into())
+    }
+}
+
+// Writes the setup params for circuits of 2^k rows
+fn setup(flags: &Flags) -> CliResult<()> {
+    let k: u32 = flags.required("k")?.parse()?;
+    let out = flags.required("out")?;
 
-    // Generate setup parameters
     let params = generate_setup_params(k);
+    let mut buf = vec![];
+    params.write(&mut buf).map_err(CardError::Params)?;
+    fs::write(out, buf)?;
+
+    println!("Wrote params k={} to {}", k, out);
+    Ok(())
+}
+
+// Proves a card and writes the proof. Prints the commitment and the
+// salt to open it with.
+fn prove(flags: &Flags) -> CliResult<()> {
+    let (suite, rank) = parse_card(flags.required("card")?)?;
+    let qty: u64 = flags.get("qty").unwrap_or("1").parse()?;
+    let salt = match flags.get("salt") {
+        Some(salt) => read_fp(salt)?,
+        None => Fp::random(OsRng),
+    };
+    let (k, params) = read_params(flags.required("params")?)?;
+    let out = flags.required("out")?;
+    let json = match flags.get("format").unwrap_or("bin") {
+        "bin" => false,
+        "json" => true,
+        format => return Err(format!("unknown format {}", format).into()),
+    };
+
+    // The only public input is the card commitment
+    let public_inputs = create_public_inputs(qty, suite, rank, salt)?;
+    let card_circuit = create_circuit(qty, suite, rank, salt)?;
+
+    // Catch unsatisfied constraints before the real prover
+    run_mock_prover(k, &card_circuit, &public_inputs)?;
+
+    let (pk, _vk) = generate_keys::<Fp>(&params, &empty_circuit())?;
+    let envelope = generate_proof(&params, &pk, card_circuit, &public_inputs)?;
+    if json {
+        fs::write(out, envelope.to_json().map_err(CardError::Envelope)?)?;
+    } else {
+        fs::write(out, envelope.to_bytes().map_err(CardError::Envelope)?)?;
+    }
+
+    println!("Wrote proof to {}", out);
+    println!("commitment: {}", to_hex(&public_inputs[0].to_repr()));
+    println!("salt: {}", to_hex(&salt.to_repr()));
+    Ok(())
+}
+
+// Verifies a proof against the card commitment
+fn verify_proof(flags: &Flags) -> CliResult<()> {
+    let (_, params) = read_params(flags.required("params")?)?;
+    let commitment = read_fp(flags.required("commitment")?)?;
+    let envelope = read_envelope(flags.required("proof")?)?;
+
+    let vk = keygen_vk(&params, &empty_circuit()).map_err(CardError::Keygen)?;
+    // Refuse to verify under another vk than the pinned one
+    if let Some(pinned) = flags.get("vk") {
+        check_vk(&vk, &from_hex(pinned)?)?;
+    }
+    verify(&params, &vk, &[commitment], &envelope)?;
+
+    println!("Proof verified");
+    Ok(())
+}
+
+// Prints what the params and proof files hold
+fn inspect(flags: &Flags) -> CliResult<()> {
+    if flags.get("params").is_none() && flags.get("proof").is_none() {
+        return Err(format!("nothing to inspect\n{}", USAGE).into());
+    }
+
+    if let Some(path) = flags.get("params") {
+        let (k, params) = read_params(path)?;
+        println!("params: k={} ({} rows)", k, 1_u64 << k);
+        println!("card vk: {}", to_hex(&card_vk_fingerprint(&params)?));
+    }
+
+    if let Some(path) = flags.get("proof") {
+        let envelope = read_envelope(path)?;
+        println!("proof: circuit {} k={} ({} bytes)", envelope.circuit_id,
+            envelope.k, envelope.proof.len());
+        println!("vk: {}", to_hex(&envelope.vk_fingerprint));
+        for input in &envelope.public_inputs {
+            println!("public input: {}", to_hex(&input.to_repr()));
+        }
+    }
+
+    Ok(())
+}
+
+// Generates the table key of the players locally, every player
+// contributes a share which is checked when aggregated
+fn dkg(flags: &Flags) -> CliResult<()> {
+    let players: u32 = flags.get("players").unwrap_or("4").parse()?;
+    let session = flags.get("session").unwrap_or("bbbluff");
+    if !(MIN_PLAYERS..=MAX_PLAYERS).contains(&(players as usize)) {
+        return Err(format!("{} players, a table seats {} to {}", players,
+            MIN_PLAYERS, MAX_PLAYERS).into());
+    }
+
+    let shares: Vec<KeyShare> = (0..players)
+        .map(|player| KeyShare::generate(player, session.as_bytes(), OsRng).1)
+        .collect();
+    let table = TableKey::aggregate(session.as_bytes(), &shares).map_err(CardError::Dkg)?;
+
+    for share in &shares {
+        println!("player {}: {}", share.player, to_hex(&share.public.to_bytes()));
+    }
+    println!("table key: {}", to_hex(&table.key().to_bytes()));
+    Ok(())
+}
+
+// Reads the params and their k, which leads the serialized params
+fn read_params(path: &str) -> CliResult<(u32, Params<EqAffine>)> {
+    let bytes = fs::read(path)?;
+    let params = Params::<EqAffine>::read(&mut BufReader::new(bytes.as_slice()))
+        .map_err(CardError::Params)?;
+    let k = u32::from_le_bytes(bytes[..4].try_into()?);
+
+    Ok((k, params))
+}
+
+// Reads a binary or JSON proof envelope
+fn read_envelope(path: &str) -> CliResult<ProofEnvelope> {
+    let bytes = fs::read(path)?;
+    let envelope = if bytes.starts_with(&ENVELOPE_MAGIC) {
+        ProofEnvelope::from_bytes(&bytes)
+    } else {
+        std::str::from_utf8(&bytes).map_err(|_| EnvelopeError::Magic)
+            .and_then(ProofEnvelope::from_json)
+    };
+
+    Ok(envelope.map_err(CardError::Envelope)?)
+}
+
+// Parses a card like QH or 10S into its (suite, rank)
+fn parse_card(card: &str) -> CliResult<(u64, u64)> {
+    let invalid = || format!("invalid card {}", card);
+
+    let split = card.len().checked_sub(1).filter(|&i| card.is_char_boundary(i))
+        .ok_or_else(invalid)?;
+    let (rank, suite) = card.split_at(split);
+
+    let suite = match suite.to_ascii_uppercase().as_str() {
+        "H" => 1,
+        "D" => 2,
+        "S" => 3,
+        "F" | "C" => 4,
+        _ => return Err(invalid().into()),
+    };
+    let rank = match rank.to_ascii_uppercase().as_str() {
+        "A" => 1,
+        "J" => 11,
+        "Q" => 12,
+        "K" => 13,
+        rank => rank.parse().ok().filter(|rank| (2..=10).contains(rank))
+            .ok_or_else(invalid)?,
+    };
+
+    Ok((suite, rank))
+}
+
+// Reads a field element from the hex of its 32 byte little endian repr
+fn read_fp(hex: &str) -> CliResult<Fp> {
+    let repr: [u8; 32] = from_hex(hex).ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(CardError::InvalidFieldElement)?;
+
+    Ok(Option::from(Fp::from_repr(repr)).ok_or(CardError::InvalidFieldElement)?)
+}
 
-    // Generate proving and verification keys
-    let empty_circuit = empty_circuit();
-    let (pk, vk) = generate_keys::<Fp>(&params, &empty_circuit);
+#[test]
+fn test_parse_card() {
+    assert_eq!(parse_card("QH").unwrap(), (1, 12));
+    assert_eq!(parse_card("10s").unwrap(), (3, 10));
+    assert_eq!(parse_card("AC").unwrap(), (4, 1));
 
-    // Generate proof
-    let proof = generate_proof(&params, &pk, card_circuit, (&public_inputs.0,
-                        &public_inputs.1, &public_inputs.2));
+    for card in ["", "H", "1H", "11D", "QX", "ZH", "Q♥"] {
+        assert!(parse_card(card).is_err(), "card {} accepted", card);
+    }
+}
 
-    let verify = verify(&params, &vk, (&public_inputs.0, &public_inputs.1,
-                        &public_inputs.2), proof);
+#[test]
+fn test_read_fp() {
+    let salt = Fp::from(0x5eed_u64);
+    assert_eq!(read_fp(&to_hex(&salt.to_repr())).unwrap(), salt);
 
-    println!("Verify results:{:?}", verify);
-}
\ No newline at end of file
+    for hex in ["00", &"ff".repeat(32), &"zz".repeat(32)] {
+        assert!(read_fp(hex).is_err());
+    }
+}
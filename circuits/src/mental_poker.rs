@@ -0,0 +1,386 @@
+use ff::{Field, FromUniformBytes};
+use group::{Group, GroupEncoding};
+use halo2_proofs::{
+    arithmetic::CurveExt,
+    pasta::pallas,
+};
+use rand_core::RngCore;
+#[cfg(test)]
+use rand_core::OsRng;
+
+use crate::card::card_code;
+use crate::deck::{standard_deck, JOKER_SUITE, JOKER_RANK};
+use crate::dkg::TableKey;
+#[cfg(test)]
+use crate::dkg::KeyShare;
+
+// ElGamal over Pallas for dealing cards face-down. A card is a curve
+// point, masked under the aggregate key of the table. Every player
+// re-masks in turn and strips their own mask when a card is revealed.
+pub type Point = pallas::Point;
+pub type Scalar = pallas::Scalar;
+
+// Domain of the card points
+const CARD_DOMAIN: &str = "bbbluff_card";
+
+// Domain of the Fiat-Shamir challenges
+const CHALLENGE_PERSONAL: &[u8; 16] = b"bbbluff_Chaum_Pd";
+
+// The point encoding card (suite, rank). Nobody knows the discrete
+// log of a card point, or the relation between two of them.
+pub fn card_point(suite: u64, rank: u64) -> Point {
+    Point::hash_to_curve(CARD_DOMAIN)(&card_code(suite, rank).to_le_bytes())
+}
+
+// The card (suite, rank) of an unmasked point, if it is one
+pub fn point_card(point: &Point) -> Option<(u64, u64)> {
+    standard_deck().into_iter()
+        .chain(std::iter::once((JOKER_SUITE, JOKER_RANK)))
+        .find(|(suite, rank)| card_point(*suite, *rank) == *point)
+}
+
+// A masked card (r * G, card + r * key)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaskedCard {
+    pub c1: Point,
+    pub c2: Point,
+}
+
+impl MaskedCard {
+    // A face-up card, masked with r = 0
+    pub fn open(card: Point) -> Self {
+        Self { c1: Point::identity(), c2: card }
+    }
+}
+
+// Fiat-Shamir challenge over the points of a proof
+fn challenge(points: &[&Point]) -> Scalar {
+    let mut state = blake2b_simd::Params::new()
+        .hash_length(64)
+        .personal(CHALLENGE_PERSONAL)
+        .to_state();
+    for point in points {
+        state.update(&point.to_bytes());
+    }
+
+    let bytes: [u8; 64] = state.finalize().as_bytes().try_into().unwrap();
+    Scalar::from_uniform_bytes(&bytes)
+}
+
+// Chaum-Pedersen proof that a = x * g and b = x * h for the same
+// secret x
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DleqProof {
+    challenge: Scalar,
+    response: Scalar,
+}
+
+impl DleqProof {
+    pub fn prove(g: &Point, h: &Point, x: &Scalar, mut rng: impl RngCore) -> Self {
+        let (a, b) = (g * x, h * x);
+        let k = Scalar::random(&mut rng);
+        let challenge = challenge(&[g, h, &a, &b, &(g * k), &(h * k)]);
+
+        Self { challenge, response: k + challenge * x }
+    }
+
+    pub fn verify(&self, g: &Point, h: &Point, a: &Point, b: &Point) -> bool {
+        let t1 = g * self.response - a * self.challenge;
+        let t2 = h * self.response - b * self.challenge;
+
+        challenge(&[g, h, a, b, &t1, &t2]) == self.challenge
+    }
+}
+
+// Proof that a masked card is a re-masking of another under the key,
+// i.e both halves moved by the same r: (r * G, r * key)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemaskProof(DleqProof);
+
+impl RemaskProof {
+    // Proves that a card was re-masked with r under the key
+    pub fn prove(key: &Point, r: &Scalar, rng: impl RngCore) -> Self {
+        Self(DleqProof::prove(&Point::generator(), key, r, rng))
+    }
+}
+
+// Adds the mask r to the card under the key
+pub fn remask_by(key: &Point, masked: &MaskedCard, r: &Scalar) -> MaskedCard {
+    MaskedCard {
+        c1: masked.c1 + Point::generator() * r,
+        c2: masked.c2 + key * r,
+    }
+}
+
+// Adds a fresh mask r to the card under the key
+pub fn remask(key: &Point, masked: &MaskedCard, mut rng: impl RngCore)
+    -> (MaskedCard, RemaskProof) {
+
+    let r = Scalar::random(&mut rng);
+
+    (remask_by(key, masked, &r), RemaskProof::prove(key, &r, rng))
+}
+
+pub fn verify_remask(key: &Point, before: &MaskedCard, after: &MaskedCard,
+    proof: &RemaskProof) -> bool {
+
+    proof.0.verify(&Point::generator(), key,
+        &(after.c1 - before.c1), &(after.c2 - before.c2))
+}
+
+// Masks a face-up card under the key
+pub fn mask(key: &Point, card: Point, rng: impl RngCore) -> (MaskedCard, RemaskProof) {
+    remask(key, &MaskedCard::open(card), rng)
+}
+
+// Removes the mask with the secret of the whole key
+pub fn unmask(secret: &Scalar, masked: &MaskedCard) -> Point {
+    masked.c2 - masked.c1 * secret
+}
+
+// The share secret * c1 of a player holding `secret` of the key,
+// with a proof that it matches their public share secret * G
+pub fn partial_unmask(secret: &Scalar, masked: &MaskedCard, rng: impl RngCore)
+    -> (Point, DleqProof) {
+
+    let proof = DleqProof::prove(&Point::generator(), &masked.c1, secret, rng);
+
+    (masked.c1 * secret, proof)
+}
+
+pub fn verify_partial_unmask(public: &Point, masked: &MaskedCard, share: &Point,
+    proof: &DleqProof) -> bool {
+
+    proof.verify(&Point::generator(), &masked.c1, public, share)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevealError {
+    // The share does not match the player's public key share
+    InvalidShare(u32),
+    // The player is not at the table
+    UnknownPlayer(u32),
+    // The player contributed more than one share
+    DuplicatePlayer(u32),
+    // The player has not contributed a share
+    MissingShare(u32),
+    // The unmasked point is not a card
+    NotACard,
+}
+
+// A player's share of the mask of a card, with the proof that it was
+// stripped with their key share
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialDecryption {
+    pub player: u32,
+    pub share: Point,
+    proof: DleqProof,
+}
+
+impl PartialDecryption {
+    pub fn create(player: u32, secret: &Scalar, masked: &MaskedCard,
+        rng: impl RngCore) -> Self {
+
+        let (share, proof) = partial_unmask(secret, masked, rng);
+
+        Self { player, share, proof }
+    }
+
+    pub fn verify(&self, table: &TableKey, masked: &MaskedCard) -> Result<(), RevealError> {
+        let public = table.public_share(self.player)
+            .ok_or(RevealError::UnknownPlayer(self.player))?;
+
+        if verify_partial_unmask(public, masked, &self.share, &self.proof) {
+            Ok(())
+        } else {
+            Err(RevealError::InvalidShare(self.player))
+        }
+    }
+}
+
+// Strips the masks of the partial decryptions from the card, checking
+// each of them. The card stays masked under the remaining players.
+pub fn combine(table: &TableKey, masked: &MaskedCard, partials: &[PartialDecryption])
+    -> Result<MaskedCard, RevealError> {
+
+    let mut combined = *masked;
+    for (i, partial) in partials.iter().enumerate() {
+        if partials[..i].iter().any(|p| p.player == partial.player) {
+            return Err(RevealError::DuplicatePlayer(partial.player));
+        }
+        partial.verify(table, masked)?;
+        combined.c2 -= partial.share;
+    }
+
+    Ok(combined)
+}
+
+// Deals the card to `recipient`: every other player must contribute.
+// The result is only masked under the recipient's share, who reveals
+// it with `unmask`.
+pub fn reveal_to(table: &TableKey, recipient: u32, masked: &MaskedCard,
+    partials: &[PartialDecryption]) -> Result<MaskedCard, RevealError> {
+
+    if partials.iter().any(|p| p.player == recipient) {
+        return Err(RevealError::DuplicatePlayer(recipient));
+    }
+    if let Some(player) = table.players().find(|player| *player != recipient &&
+        !partials.iter().any(|p| p.player == *player)) {
+        return Err(RevealError::MissingShare(player));
+    }
+
+    combine(table, masked, partials)
+}
+
+// Turns the card face up, every player must contribute
+pub fn open_card(table: &TableKey, masked: &MaskedCard,
+    partials: &[PartialDecryption]) -> Result<(u64, u64), RevealError> {
+
+    if let Some(player) = table.players()
+        .find(|player| !partials.iter().any(|p| p.player == *player)) {
+        return Err(RevealError::MissingShare(player));
+    }
+
+    let combined = combine(table, masked, partials)?;
+    point_card(&combined.c2).ok_or(RevealError::NotACard)
+}
+
+#[cfg(test)]
+fn table(players: usize) -> (Vec<Scalar>, Point) {
+    let secrets: Vec<Scalar> = (0..players).map(|_| Scalar::random(OsRng)).collect();
+    let key = secrets.iter().map(|s| Point::generator() * s).sum();
+
+    (secrets, key)
+}
+
+#[test]
+fn test_card_points() {
+    for (suite, rank) in standard_deck() {
+        assert_eq!(point_card(&card_point(suite, rank)), Some((suite, rank)));
+    }
+    assert_eq!(point_card(&card_point(JOKER_SUITE, JOKER_RANK)), Some((JOKER_SUITE, JOKER_RANK)));
+    assert_eq!(point_card(&Point::generator()), None);
+}
+
+#[test]
+fn test_mask_remask_unmask() {
+    let (secrets, key) = table(3);
+    let card = card_point(2, 7);
+
+    let (mut masked, proof) = mask(&key, card, OsRng);
+    assert!(verify_remask(&key, &MaskedCard::open(card), &masked, &proof));
+
+    // Every player re-masks in turn
+    for _ in &secrets {
+        let (remasked, proof) = remask(&key, &masked, OsRng);
+        assert_ne!(remasked, masked);
+        assert!(verify_remask(&key, &masked, &remasked, &proof));
+        masked = remasked;
+    }
+
+    let secret: Scalar = secrets.iter().sum();
+    assert_eq!(point_card(&unmask(&secret, &masked)), Some((2, 7)));
+
+    // Each player strips their share of the mask
+    let shares: Vec<Point> = secrets.iter().map(|s| {
+        let (share, proof) = partial_unmask(s, &masked, OsRng);
+        assert!(verify_partial_unmask(&(Point::generator() * s), &masked,
+            &share, &proof));
+        share
+    }).collect();
+    let card = masked.c2 - shares.iter().sum::<Point>();
+    assert_eq!(point_card(&card), Some((2, 7)));
+}
+
+#[test]
+fn test_bad_proofs_rejected() {
+    let (secrets, key) = table(2);
+    let (masked, _) = mask(&key, card_point(1, 1), OsRng);
+
+    // Re-masking that swaps the card
+    let (remasked, proof) = remask(&key, &masked, OsRng);
+    let swapped = MaskedCard {
+        c1: remasked.c1,
+        c2: remasked.c2 - card_point(1, 1) + card_point(4, 13),
+    };
+    assert!(!verify_remask(&key, &masked, &swapped, &proof));
+
+    // A share from a different secret than the public share
+    let (share, proof) = partial_unmask(&secrets[0], &masked, OsRng);
+    let public = Point::generator() * secrets[1];
+    assert!(!verify_partial_unmask(&public, &masked, &share, &proof));
+    let wrong = share + Point::generator();
+    let public = Point::generator() * secrets[0];
+    assert!(!verify_partial_unmask(&public, &masked, &wrong, &proof));
+}
+
+#[cfg(test)]
+fn dealt_table(players: u32) -> (Vec<Scalar>, TableKey, MaskedCard) {
+    const SESSION: &[u8] = b"deal";
+
+    let (secrets, shares): (Vec<Scalar>, Vec<KeyShare>) = (0..players)
+        .map(|player| KeyShare::generate(player, SESSION, OsRng))
+        .unzip();
+    let table = TableKey::aggregate(SESSION, &shares).unwrap();
+
+    // Masked once and re-masked by every player
+    let (mut masked, _) = mask(table.key(), card_point(3, 12), OsRng);
+    for _ in 0..players {
+        masked = remask(table.key(), &masked, OsRng).0;
+    }
+
+    (secrets, table, masked)
+}
+
+#[test]
+fn test_reveal_to_one_player() {
+    for players in [2, 4, 8] {
+        let (secrets, table, masked) = dealt_table(players);
+
+        for recipient in 0..players {
+            let partials: Vec<PartialDecryption> = (0..players)
+                .filter(|player| *player != recipient)
+                .map(|player| PartialDecryption::create(player,
+                    &secrets[player as usize], &masked, OsRng))
+                .collect();
+
+            let dealt = reveal_to(&table, recipient, &masked, &partials).unwrap();
+            // Still hidden to everyone but the recipient
+            assert_eq!(point_card(&dealt.c2), None);
+            let card = unmask(&secrets[recipient as usize], &dealt);
+            assert_eq!(point_card(&card), Some((3, 12)));
+
+            // Without one of the other players the card stays masked
+            assert_eq!(reveal_to(&table, recipient, &masked, &partials[1..]),
+                Err(RevealError::MissingShare(partials[0].player)));
+        }
+
+        let partials: Vec<PartialDecryption> = (0..players)
+            .map(|player| PartialDecryption::create(player,
+                &secrets[player as usize], &masked, OsRng))
+            .collect();
+        assert_eq!(open_card(&table, &masked, &partials), Ok((3, 12)));
+    }
+}
+
+#[test]
+fn test_reveal_rejects_bad_shares() {
+    let (secrets, table, masked) = dealt_table(4);
+    let mut partials: Vec<PartialDecryption> = (1..4)
+        .map(|player| PartialDecryption::create(player,
+            &secrets[player as usize], &masked, OsRng))
+        .collect();
+
+    // Player 2 strips a different mask than their key share
+    partials[1].share += Point::generator();
+    assert_eq!(reveal_to(&table, 0, &masked, &partials),
+        Err(RevealError::InvalidShare(2)));
+
+    partials[1] = PartialDecryption::create(2, &secrets[2], &masked, OsRng);
+    partials.push(partials[0]);
+    assert_eq!(reveal_to(&table, 0, &masked, &partials),
+        Err(RevealError::DuplicatePlayer(1)));
+
+    let stranger = PartialDecryption::create(9, &secrets[0], &masked, OsRng);
+    assert_eq!(stranger.verify(&table, &masked), Err(RevealError::UnknownPlayer(9)));
+}
@@ -0,0 +1,274 @@
+use std::marker::PhantomData;
+use halo2_proofs::{
+    circuit::{Layouter, Value},
+    plonk::{Advice, Assigned, Column, ConstraintSystem, Constraints,
+        Error, Expression, Fixed, Selector},
+    poly::Rotation,
+};
+#[cfg(test)]
+use halo2_proofs::pasta::Fp;
+
+use crate::card::CardError;
+use crate::poseidon::{self, PoseidonChip, PoseidonConfig, PoseidonField, Word};
+
+// A node is the hash of its two children
+pub fn merkle_node<F: PoseidonField>(left: F, right: F) -> F {
+    poseidon::hash([left, right])
+}
+
+// Roots of the empty subtrees, level 0 being an empty leaf (0)
+pub fn empty_nodes<F: PoseidonField>(depth: usize) -> Vec<F> {
+    let mut nodes = vec![F::ZERO];
+    for level in 0..depth {
+        nodes.push(merkle_node(nodes[level], nodes[level]));
+    }
+
+    nodes
+}
+
+// Whether a tree of `depth` holds between 1 and 2^depth leaves
+fn fits(leaves: usize, depth: usize) -> bool {
+    leaves > 0 && u32::try_from(depth).ok()
+        .and_then(|depth| (leaves - 1).checked_shr(depth))
+        .is_none_or(|high| high == 0)
+}
+
+// Every level of the tree of `depth` over the leaves, the leaves
+// first and the root last. Missing leaves are empty.
+fn merkle_levels<F: PoseidonField>(leaves: &[F], depth: usize)
+    -> Result<Vec<Vec<F>>, CardError> {
+
+    if !fits(leaves.len(), depth) {
+        return Err(CardError::TreeSize { leaves: leaves.len(), depth });
+    }
+
+    let empty = empty_nodes::<F>(depth);
+    let mut levels = vec![leaves.to_vec()];
+    for pad in empty.iter().take(depth) {
+        let mut level = levels.last().unwrap().clone();
+        if level.len() % 2 == 1 {
+            level.push(*pad);
+        }
+        levels.push(level.chunks(2).map(|pair| merkle_node(pair[0], pair[1])).collect());
+    }
+
+    Ok(levels)
+}
+
+// Root of the tree of `depth` over the leaves
+pub fn merkle_root<F: PoseidonField>(leaves: &[F], depth: usize) -> Result<F, CardError> {
+    Ok(merkle_levels(leaves, depth)?[depth][0])
+}
+
+// Siblings of the leaf at `position`, from the leaf level up
+pub fn merkle_path<F: PoseidonField>(leaves: &[F], depth: usize, position: usize)
+    -> Result<Vec<F>, CardError> {
+
+    if position >= leaves.len() {
+        return Err(CardError::NoSuchLeaf(position));
+    }
+
+    let empty = empty_nodes::<F>(depth);
+    let levels = merkle_levels(leaves, depth)?;
+    Ok((0..depth).map(|level| {
+        let sibling = (position >> level) ^ 1;
+        levels[level].get(sibling).copied().unwrap_or(empty[level])
+    }).collect())
+}
+
+#[derive(Debug, Clone)]
+pub struct MerkleConfig<F: PoseidonField> {
+    node: Column<Advice>,
+    sibling: Column<Advice>,
+    bit: Column<Advice>,
+    left: Column<Advice>,
+    right: Column<Advice>,
+    index_prev: Column<Advice>,
+    index: Column<Advice>,
+    weight: Column<Fixed>,
+    poseidon: PoseidonConfig<F>,
+    s_level: Selector,
+}
+
+// Builds Merkle roots and checks authentication paths over
+// Poseidon nodes
+#[derive(Debug, Clone)]
+pub(crate) struct MerkleChip<F: PoseidonField> {
+    config: MerkleConfig<F>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> MerkleChip<F> {
+
+    pub fn construct(config: MerkleConfig<F>) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>,
+                poseidon: PoseidonConfig<F>) -> MerkleConfig<F> {
+
+        let node = meta.advice_column();
+        let sibling = meta.advice_column();
+        let bit = meta.advice_column();
+        let left = meta.advice_column();
+        let right = meta.advice_column();
+        let index_prev = meta.advice_column();
+        let index = meta.advice_column();
+        let weight = meta.fixed_column();
+        let s_level = meta.selector();
+
+        for column in [node, left, right, index_prev, index] {
+            meta.enable_equality(column);
+        }
+
+        let one = Expression::Constant(F::ONE);
+
+        // | node | sibling | bit | left | right | index_prev | index | weight |
+        // |------|---------|-----|------|-------|------------|-------|--------|
+        // |  n   |    s    |  b  |  l   |   r   |     i      |  i'   |  2^d   |
+        //
+        // (l, r) = (n, s) if b = 0, (s, n) if b = 1 and i' = i + b * 2^d,
+        // so the bits of the path add up to the leaf position.
+        meta.create_gate("merkle level", |meta| {
+            let s = meta.query_selector(s_level);
+            let n = meta.query_advice(node, Rotation::cur());
+            let sib = meta.query_advice(sibling, Rotation::cur());
+            let b = meta.query_advice(bit, Rotation::cur());
+            let l = meta.query_advice(left, Rotation::cur());
+            let r = meta.query_advice(right, Rotation::cur());
+            let i = meta.query_advice(index_prev, Rotation::cur());
+            let i_next = meta.query_advice(index, Rotation::cur());
+            let w = meta.query_fixed(weight);
+
+            Constraints::with_selector(s, [
+                ("bit", b.clone() * (one.clone() - b.clone())),
+                ("left", l - (n.clone() + b.clone() * (sib.clone() - n.clone()))),
+                ("right", r - (sib.clone() + b.clone() * (n - sib))),
+                ("index", i_next - (i + b * w)),
+            ])
+        });
+
+        MerkleConfig {
+            node,
+            sibling,
+            bit,
+            left,
+            right,
+            index_prev,
+            index,
+            weight,
+            poseidon,
+            s_level,
+        }
+    }
+
+    // Root of the tree of `depth` over the leaf cells, matching
+    // `merkle_root`
+    pub fn root(&self, mut layouter: impl Layouter<F>, leaves: &[Word<F>],
+        depth: usize) -> Result<Word<F>, Error> {
+
+        if !fits(leaves.len(), depth) {
+            return Err(Error::Synthesis);
+        }
+
+        let poseidon = PoseidonChip::construct(self.config.poseidon.clone());
+        let empty = empty_nodes::<F>(depth);
+        let mut level = leaves.to_vec();
+
+        for (d, pad) in empty.iter().take(depth).enumerate() {
+            if level.len() % 2 == 1 {
+                level.push(layouter.assign_region(|| "empty node", |mut region| {
+                    region.assign_advice_from_constant(|| "empty", self.config.node,
+                        0, Assigned::from(*pad))
+                })?);
+            }
+
+            level = level.chunks_exact(2).enumerate().map(|(i, pair)| {
+                let pair = [pair[0].clone(), pair[1].clone()];
+                poseidon.hash(layouter.namespace(|| format!("node {} {}", d, i)), &pair)
+            }).collect::<Result<_, _>>()?;
+        }
+
+        Ok(level.remove(0))
+    }
+
+    // Recomputes the root from the leaf, its position and the siblings
+    // from the leaf level up. Returns the root and the position cells.
+    pub fn path(&self, mut layouter: impl Layouter<F>, leaf: &Word<F>,
+        position: Value<u64>, siblings: &[Value<Assigned<F>>])
+        -> Result<(Word<F>, Word<F>), Error> {
+
+        let poseidon = PoseidonChip::construct(self.config.poseidon.clone());
+        let mut node = leaf.clone();
+        let mut index: Option<Word<F>> = None;
+
+        for (d, sibling) in siblings.iter().enumerate() {
+            let swap = position.map(|p| (p >> d) & 1 == 1);
+            let bit = swap.map(|b| Assigned::from(F::from(b as u64)));
+
+            let (left, right, next) = layouter.assign_region(
+                || format!("merkle level {}", d), |mut region| {
+                self.config.s_level.enable(&mut region, 0)?;
+
+                let n = node.copy_advice(|| "node", &mut region, self.config.node, 0)?;
+                region.assign_advice(|| "sibling", self.config.sibling, 0, || *sibling)?;
+                region.assign_advice(|| "bit", self.config.bit, 0, || bit)?;
+                region.assign_fixed(|| "weight", self.config.weight, 0,
+                    || Value::known(F::from(1 << d)))?;
+
+                let (l, r) = n.value().copied().zip(*sibling).zip(swap)
+                    .map(|((n, s), swap)| if swap { (s, n) } else { (n, s) })
+                    .unzip();
+                let left = region.assign_advice(|| "left", self.config.left, 0, || l)?;
+                let right = region.assign_advice(|| "right", self.config.right, 0, || r)?;
+
+                let prev = match &index {
+                    Some(cell) => cell.copy_advice(|| "index", &mut region,
+                        self.config.index_prev, 0)?,
+                    None => region.assign_advice_from_constant(|| "index",
+                        self.config.index_prev, 0, Assigned::from(F::ZERO))?,
+                };
+                let next = region.assign_advice(|| "index", self.config.index, 0,
+                    || prev.value().copied() + bit * Value::known(Assigned::from(F::from(1 << d))))?;
+
+                Ok((left, right, next))
+            })?;
+
+            node = poseidon.hash(layouter.namespace(|| format!("merkle node {}", d)),
+                &[left, right])?;
+            index = Some(next);
+        }
+
+        Ok((node, index.ok_or(Error::Synthesis)?))
+    }
+}
+
+#[test]
+fn test_path_recomputes_root() {
+    let leaves: Vec<Fp> = (1..=5).map(Fp::from).collect();
+    let root = merkle_root(&leaves, 3).unwrap();
+
+    for (position, leaf) in leaves.iter().enumerate() {
+        let path = merkle_path(&leaves, 3, position).unwrap();
+        let node = path.iter().enumerate().fold(*leaf, |node, (d, sibling)| {
+            if (position >> d) & 1 == 1 {
+                merkle_node(*sibling, node)
+            } else {
+                merkle_node(node, *sibling)
+            }
+        });
+        assert_eq!(node, root);
+    }
+
+    // Empty leaves pad the tree
+    let mut padded = leaves.clone();
+    padded.extend([Fp::from(0); 3]);
+    assert_eq!(merkle_root(&padded, 3).unwrap(), root);
+
+    // No tree without leaves, over too many leaves or path of a
+    // missing leaf
+    assert!(matches!(merkle_root::<Fp>(&[], 3),
+        Err(CardError::TreeSize { leaves: 0, depth: 3 })));
+    assert!(matches!(merkle_root(&leaves, 2), Err(CardError::TreeSize { leaves: 5, depth: 2 })));
+    assert!(matches!(merkle_path(&leaves, 3, 5), Err(CardError::NoSuchLeaf(5))));
+}
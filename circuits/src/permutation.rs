@@ -0,0 +1,204 @@
+use std::marker::PhantomData;
+use ff::PrimeField;
+use halo2_proofs::{
+    circuit::{Layouter, Region, Value},
+    plonk::{Advice, Assigned, Column, ConstraintSystem, Constraints,
+        Error, Expression, Selector, VirtualCells},
+    poly::Rotation,
+};
+
+use crate::card::CODE_SUITE_STRIDE;
+use crate::poseidon::Word;
+
+// The (suite, rank) cells of a card
+pub(crate) type CardCells<F> = (Word<F>, Word<F>);
+
+// Cards on one side of the permutation check, either assigned
+// (suite, rank) cells, known (suite, rank) values or cells already
+// holding a card code
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Cards<'a, F: PrimeField> {
+    Assigned(&'a [CardCells<F>]),
+    Constant(&'a [(u64, u64)]),
+    Codes(&'a [Word<F>]),
+}
+
+impl<F: PrimeField> Cards<'_, F> {
+    fn len(&self) -> usize {
+        match self {
+            Cards::Assigned(cards) => cards.len(),
+            Cards::Constant(cards) => cards.len(),
+            Cards::Codes(codes) => codes.len(),
+        }
+    }
+
+    // Places the card at `offset` in the suite and rank columns
+    fn assign(&self, region: &mut Region<'_, F>, offset: usize,
+        columns: (Column<Advice>, Column<Advice>)) -> Result<CardCells<F>, Error> {
+
+        match self {
+            Cards::Assigned(cards) => Ok((
+                cards[offset].0.copy_advice(|| "suite", region, columns.0, offset)?,
+                cards[offset].1.copy_advice(|| "rank", region, columns.1, offset)?,
+            )),
+            Cards::Constant(cards) => Ok((
+                region.assign_advice_from_constant(|| "suite", columns.0, offset,
+                    Assigned::from(F::from(cards[offset].0)))?,
+                region.assign_advice_from_constant(|| "rank", columns.1, offset,
+                    Assigned::from(F::from(cards[offset].1)))?,
+            )),
+            // code = 0 * 16 + code
+            Cards::Codes(codes) => Ok((
+                region.assign_advice_from_constant(|| "suite", columns.0, offset,
+                    Assigned::from(F::ZERO))?,
+                codes[offset].copy_advice(|| "code", region, columns.1, offset)?,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PermutationConfig {
+    left: (Column<Advice>, Column<Advice>),
+    right: (Column<Advice>, Column<Advice>),
+    challenge: Column<Advice>,
+    left_product: Column<Advice>,
+    right_product: Column<Advice>,
+    s_first: Selector,
+    s_next: Selector,
+}
+
+// Proves that two sequences of cards are permutations of each other
+#[derive(Debug, Clone)]
+pub(crate) struct PermutationChip<F: PrimeField> {
+    config: PermutationConfig,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> PermutationChip<F> {
+
+    pub fn construct(config: PermutationConfig) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>) -> PermutationConfig {
+
+        let left = (meta.advice_column(), meta.advice_column());
+        let right = (meta.advice_column(), meta.advice_column());
+        let challenge = meta.advice_column();
+        let left_product = meta.advice_column();
+        let right_product = meta.advice_column();
+        let s_first = meta.selector();
+        let s_next = meta.selector();
+
+        for column in [left.0, left.1, right.0, right.1, challenge,
+                left_product, right_product] {
+            meta.enable_equality(column);
+        }
+
+        let stride = Expression::Constant(F::from(CODE_SUITE_STRIDE));
+
+        // | suite | rank | suite' | rank' | challenge | product | product' |
+        // |-------|------|--------|-------|-----------|---------|----------|
+        // |  s0   |  r0  |  s0'   |  r0'  |     x     |   p0    |   p0'    |
+        // |  s1   |  r1  |  s1'   |  r1'  |     x     |   p1    |   p1'    |
+        //
+        // p = prod (x - code(s, r)) over the left cards and p' the same
+        // over the right cards. For a challenge x derived from both
+        // committed sequences, p = p' only if they hold the same cards.
+        let factors = |meta: &mut VirtualCells<F>| {
+            let x = meta.query_advice(challenge, Rotation::cur());
+            let code = |meta: &mut VirtualCells<F>, (s, r): (Column<Advice>, Column<Advice>)| {
+                meta.query_advice(s, Rotation::cur()) * stride.clone() +
+                    meta.query_advice(r, Rotation::cur())
+            };
+            (x.clone() - code(meta, left), x - code(meta, right))
+        };
+
+        meta.create_gate("permutation first card", |meta| {
+            let s = meta.query_selector(s_first);
+            let (l, r) = factors(meta);
+            let p = meta.query_advice(left_product, Rotation::cur());
+            let q = meta.query_advice(right_product, Rotation::cur());
+
+            Constraints::with_selector(s, [
+                ("left product", p - l),
+                ("right product", q - r),
+            ])
+        });
+
+        meta.create_gate("permutation next card", |meta| {
+            let s = meta.query_selector(s_next);
+            let (l, r) = factors(meta);
+            let p_prev = meta.query_advice(left_product, Rotation::prev());
+            let p = meta.query_advice(left_product, Rotation::cur());
+            let q_prev = meta.query_advice(right_product, Rotation::prev());
+            let q = meta.query_advice(right_product, Rotation::cur());
+
+            Constraints::with_selector(s, [
+                ("left product", p - p_prev * l),
+                ("right product", q - q_prev * r),
+            ])
+        });
+
+        PermutationConfig {
+            left,
+            right,
+            challenge,
+            left_product,
+            right_product,
+            s_first,
+            s_next,
+        }
+    }
+
+    // Constrains `left` to be a permutation of `right` under the
+    // challenge, which must be bound to both sides
+    pub fn assign(&self, mut layouter: impl Layouter<F>, challenge: &Word<F>,
+        left: Cards<'_, F>, right: Cards<'_, F>) -> Result<(), Error> {
+
+        if left.len() != right.len() {
+            return Err(Error::Synthesis);
+        }
+
+        let stride = Value::known(Assigned::from(F::from(CODE_SUITE_STRIDE)));
+        let code = |(s, r): &CardCells<F>| {
+            s.value().copied() * stride + r.value().copied()
+        };
+
+        let (p, q) = layouter.assign_region(|| "Permutation", |mut region| {
+            let mut p = Value::known(Assigned::from(F::ONE));
+            let mut q = Value::known(Assigned::from(F::ONE));
+            let mut cells = None;
+
+            for offset in 0..left.len() {
+                if offset == 0 {
+                    self.config.s_first.enable(&mut region, offset)?;
+                } else {
+                    self.config.s_next.enable(&mut region, offset)?;
+                }
+
+                let l = left.assign(&mut region, offset, self.config.left)?;
+                let r = right.assign(&mut region, offset, self.config.right)?;
+                let x = challenge.copy_advice(|| "challenge", &mut region,
+                    self.config.challenge, offset)?;
+
+                p = p * (x.value().copied() - code(&l));
+                q = q * (x.value().copied() - code(&r));
+
+                cells = Some((
+                    region.assign_advice(|| "left product",
+                        self.config.left_product, offset, || p)?,
+                    region.assign_advice(|| "right product",
+                        self.config.right_product, offset, || q)?,
+                ));
+            }
+
+            cells.ok_or(Error::Synthesis)
+        })?;
+
+        layouter.assign_region(|| "Permutation matches", |mut region| {
+            region.constrain_equal(p.cell(), q.cell())
+        })
+    }
+}
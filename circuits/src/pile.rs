@@ -0,0 +1,405 @@
+use std::marker::PhantomData;
+use ff::Field;
+use halo2_proofs::{
+    circuit::{Layouter, Value, SimpleFloorPlanner},
+    plonk::{Advice, Instance, Assigned, Column, ConstraintSystem,
+        Constraints, Error, Expression, Selector, Circuit},
+    poly::Rotation,
+    pasta::Fp,
+};
+#[cfg(test)]
+use halo2_proofs::dev::MockProver;
+
+use crate::card::{CardChip, CardConfig, CardError, configure_card};
+use crate::deck::{card_commitments, DeckSpec};
+use crate::hand::{Hand, HandCards, HandChip, HandConfig};
+use crate::permutation::Cards;
+use crate::poseidon::{self, PoseidonChip, PoseidonField, Word};
+
+// The pile can hold every card of the deck
+pub fn pile_slots(spec: &DeckSpec) -> usize {
+    spec.size()
+}
+
+// Rows of the public input column
+pub const PILE_ROW: usize = 0;
+pub const PICKUP_OLD_HAND_ROW: usize = 1;
+pub const PICKUP_NEW_HAND_ROW: usize = 2;
+
+// The discard pile is a hash chain over the commitments of the cards
+// put down, H(H(0, c0), c1)... Anyone can follow it from the public
+// commitments of each claim.
+pub fn empty_pile() -> Fp {
+    Fp::ZERO
+}
+
+// The pile after putting down the committed cards, in order
+pub fn pile_push(pile: Fp, commitments: &[Fp]) -> Fp {
+    commitments.iter().fold(pile, |pile, c| poseidon::hash([pile, *c]))
+}
+
+// The pile holding the cards (suite, rank, salt), in order
+pub fn pile_of(cards: &[(u64, u64, Fp)]) -> Fp {
+    pile_push(empty_pile(), &card_commitments(cards))
+}
+
+#[derive(Debug, Clone)]
+pub struct PileConfig<F: PoseidonField> {
+    card: CardConfig<F>,
+    active_prev: Column<Advice>,
+    active: Column<Advice>,
+    pile: Column<Advice>,
+    pushed: Column<Advice>,
+    next: Column<Advice>,
+    s_push: Selector,
+}
+
+// The pile slots: whether each one holds a card, and its card
+#[derive(Debug, Clone)]
+pub(crate) struct PileSlots<F: PoseidonField> {
+    pub pile: Word<F>,
+    pub active: Vec<Word<F>>,
+    pub suite: Vec<Word<F>>,
+    pub rank: Vec<Word<F>>,
+}
+
+// Opens the pile card by card
+#[derive(Debug, Clone)]
+pub(crate) struct PileChip<F: PoseidonField> {
+    config: PileConfig<F>,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> PileChip<F> {
+
+    pub fn construct(config: PileConfig<F>) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>, card: CardConfig<F>) -> PileConfig<F> {
+
+        let active_prev = meta.advice_column();
+        let active = meta.advice_column();
+        let pile = meta.advice_column();
+        let pushed = meta.advice_column();
+        let next = meta.advice_column();
+        let s_push = meta.selector();
+
+        for column in [active_prev, active, pile, pushed, next] {
+            meta.enable_equality(column);
+        }
+
+        let one = Expression::Constant(F::ONE);
+
+        // | active_prev | active | pile | pushed | next | selector |
+        // |-------------|--------|------|--------|------|----------|
+        // |     a'      |   a    |  p   |   h    |  n   |  s_push  |
+        //
+        // h = H(p, c) is the pile with the slot's card on top, kept only
+        // for an active slot: n = p + a * (h - p). Active slots come first.
+        meta.create_gate("pile push", |meta| {
+            let s = meta.query_selector(s_push);
+            let a_prev = meta.query_advice(active_prev, Rotation::cur());
+            let a = meta.query_advice(active, Rotation::cur());
+            let p = meta.query_advice(pile, Rotation::cur());
+            let h = meta.query_advice(pushed, Rotation::cur());
+            let n = meta.query_advice(next, Rotation::cur());
+
+            Constraints::with_selector(s, [
+                ("active is boolean", a.clone() * (one.clone() - a.clone())),
+                ("active prefix", a.clone() * (one.clone() - a_prev)),
+                ("next pile", n - (p.clone() + a * (h - p))),
+            ])
+        });
+
+        PileConfig {
+            card,
+            active_prev,
+            active,
+            pile,
+            pushed,
+            next,
+            s_push,
+        }
+    }
+
+    pub fn load_tables(&self, layouter: impl Layouter<F>, spec: &DeckSpec)
+        -> Result<(), Error> {
+
+        CardChip::construct(self.config.card.clone()).load_tables(layouter, spec)
+    }
+
+    // Commits the cards of the pile and rebuilds the pile from the
+    // empty one
+    pub fn assign(&self, mut layouter: impl Layouter<F>, cards: &[PileCard<F>])
+        -> Result<PileSlots<F>, Error> {
+
+        let card_chip = CardChip::construct(self.config.card.clone());
+        let poseidon = PoseidonChip::construct(self.config.card.poseidon().clone());
+
+        let (mut pile, mut active_prev) = layouter.assign_region(|| "Empty pile",
+            |mut region| {
+            Ok((
+                region.assign_advice_from_constant(|| "pile", self.config.next, 0,
+                    Assigned::from(F::ZERO))?,
+                region.assign_advice_from_constant(|| "active", self.config.active, 0,
+                    Assigned::from(F::ONE))?,
+            ))
+        })?;
+
+        let mut active = Vec::with_capacity(cards.len());
+        let mut suite = Vec::with_capacity(cards.len());
+        let mut rank = Vec::with_capacity(cards.len());
+
+        for (i, card) in cards.iter().enumerate() {
+            // Pile cards are single cards
+            let cell = card_chip.assign(
+                layouter.namespace(|| format!("Pile card {}", i)),
+                Value::known(F::ONE.into()), card.suite, card.rank, card.salt)?;
+            layouter.assign_region(|| "single card", |mut region| {
+                region.constrain_constant(cell.qty.cell(), F::ONE)
+            })?;
+
+            let pushed = poseidon.hash(layouter.namespace(|| format!("Pile push {}", i)),
+                &[pile.clone(), cell.commitment.clone()])?;
+
+            let (a, next) = layouter.assign_region(|| "Pile slot", |mut region| {
+                self.config.s_push.enable(&mut region, 0)?;
+
+                active_prev.copy_advice(|| "active prev", &mut region,
+                    self.config.active_prev, 0)?;
+                let a = region.assign_advice(|| "active", self.config.active, 0,
+                    || card.active)?;
+                let p = pile.copy_advice(|| "pile", &mut region, self.config.pile, 0)?;
+                let h = pushed.copy_advice(|| "pushed", &mut region,
+                    self.config.pushed, 0)?;
+
+                let n = p.value().copied() +
+                    card.active * (h.value().copied() - p.value().copied());
+                let next = region.assign_advice(|| "next", self.config.next, 0, || n)?;
+
+                Ok((a, next))
+            })?;
+
+            active.push(a.clone());
+            suite.push(cell.suite);
+            rank.push(cell.rank);
+            active_prev = a;
+            pile = next;
+        }
+
+        Ok(PileSlots { pile, active, suite, rank })
+    }
+}
+
+// A card in the pile. Unused slots are inactive and hold any legal card.
+#[derive(Debug, Clone, Copy)]
+pub struct PileCard<F: PoseidonField> {
+    active: Value<Assigned<F>>,
+    suite: Value<Assigned<F>>,
+    rank: Value<Assigned<F>>,
+    salt: Value<Assigned<F>>,
+}
+
+impl<F: PoseidonField> Default for PileCard<F> {
+    fn default() -> Self {
+        Self {
+            active: Value::unknown(),
+            suite: Value::unknown(),
+            rank: Value::unknown(),
+            salt: Value::unknown(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PickUpConfig<F: PoseidonField> {
+    pile: PileConfig<F>,
+    hand: HandConfig<F>,
+    instance: Column<Instance>,
+}
+
+// Proves that the new hand of the player picking up the pile is the
+// old hand plus every card of the pile. The openings of the pile cards
+// are handed to the player by those who put them down.
+pub struct PickUpCircuit<F: PoseidonField> {
+    spec: DeckSpec,
+    player: Value<Assigned<F>>,
+    session: Value<Assigned<F>>,
+    old: HandCards<F>,
+    new: HandCards<F>,
+    cards: Vec<PileCard<F>>,
+}
+
+impl<F: PoseidonField> PickUpCircuit<F> {
+    // The circuit of the spec without witnesses, for key generation
+    pub fn empty(spec: DeckSpec) -> Self {
+        Self {
+            spec,
+            player: Value::unknown(),
+            session: Value::unknown(),
+            old: HandCards::empty(&spec),
+            new: HandCards::empty(&spec),
+            cards: vec![PileCard::default(); pile_slots(&spec)],
+        }
+    }
+}
+
+impl<F: PoseidonField> Default for PickUpCircuit<F> {
+    fn default() -> Self {
+        Self::empty(DeckSpec::standard())
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for PickUpCircuit<F> {
+
+    type Config = PickUpConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::empty(self.spec)
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let instance = meta.instance_column();
+        let card = configure_card(meta, instance);
+        let hand = HandChip::configure(meta, card.poseidon().clone());
+        let pile = PileChip::configure(meta, card);
+
+        PickUpConfig { pile, hand, instance }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let pile = PileChip::construct(config.pile);
+        let hand: HandChip<F> = HandChip::construct(config.hand);
+        pile.load_tables(layouter.namespace(|| "Card Tables"), &self.spec)?;
+
+        let slots = pile.assign(layouter.namespace(|| "Pile"), &self.cards)?;
+
+        let owner = hand.assign_owner(layouter.namespace(|| "Owner"),
+            self.player, self.session)?;
+        let (old, old_slots) = hand.commit(layouter.namespace(|| "Old hand"),
+            &owner, &self.old)?;
+        let (new, new_slots) = hand.commit(layouter.namespace(|| "New hand"),
+            &owner, &self.new)?;
+
+        // new + empty slots = old + pile, the pile fills empty slots
+        let mut left = new_slots;
+        left.extend(hand.empty(layouter.namespace(|| "Empty"), self.cards.len())?);
+        let mut right = old_slots;
+        right.extend(hand.played_codes(layouter.namespace(|| "Pile codes"),
+            &slots.active, &slots.suite, &slots.rank)?);
+
+        // The challenge is bound to the pile and both hands
+        let challenge = hand.poseidon().hash(
+            layouter.namespace(|| "Pick up challenge"),
+            &[slots.pile.clone(), old.clone(), new.clone()])?;
+
+        hand.permutation().assign(layouter.namespace(|| "Pick up check"),
+            &challenge, Cards::Codes(&left), Cards::Codes(&right))?;
+
+        layouter.constrain_instance(slots.pile.cell(), config.instance, PILE_ROW)?;
+        layouter.constrain_instance(old.cell(), config.instance, PICKUP_OLD_HAND_ROW)?;
+        layouter.constrain_instance(new.cell(), config.instance, PICKUP_NEW_HAND_ROW)
+    }
+}
+
+// Creates the pick up circuit for the pile cards (suite, rank, salt),
+// in the order they were put down
+pub fn create_pickup_circuit(old: &Hand, new: &Hand, cards: &[(u64, u64, Fp)])
+    -> Result<PickUpCircuit<Fp>, CardError> {
+
+    old.check_owner(new)?;
+    if cards.len() > pile_slots(&old.spec) {
+        return Err(CardError::PileSize(cards.len()));
+    }
+
+    let mut circuit = PickUpCircuit {
+        player: Value::known(Fp::from(old.player).into()),
+        session: Value::known(old.session.into()),
+        old: old.witness()?,
+        new: new.witness()?,
+        ..PickUpCircuit::empty(old.spec)
+    };
+    for (i, slot) in circuit.cards.iter_mut().enumerate() {
+        // Pad with an inactive Ace of Hearts
+        let (active, (suite, rank, salt)) = match cards.get(i) {
+            Some(card) => (1, *card),
+            None => (0, (1, 1, Fp::ZERO)),
+        };
+
+        *slot = PileCard {
+            active: Value::known(Fp::from(active).into()),
+            suite: Value::known(Fp::from(suite).into()),
+            rank: Value::known(Fp::from(rank).into()),
+            salt: Value::known(salt.into()),
+        };
+    }
+
+    Ok(circuit)
+}
+
+// Formats the public inputs, the pile followed by the old and the
+// new hand commitments
+pub fn create_pickup_public_inputs(pile: Fp, old: &Hand, new: &Hand)
+    -> Result<Vec<Fp>, CardError> {
+
+    old.check_owner(new)?;
+
+    Ok(vec![pile, old.commitment()?, new.commitment()?])
+}
+
+#[cfg(test)]
+fn run_pickup(pile: Fp, old: &Hand, new: &Hand, cards: &[(u64, u64, Fp)])
+    -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 15;
+
+    let circuit = create_pickup_circuit(old, new, cards).unwrap();
+    let public_inputs = create_pickup_public_inputs(pile, old, new).unwrap();
+    MockProver::run(K, &circuit, vec![public_inputs]).unwrap().verify()
+}
+
+#[cfg(test)]
+fn loser_hand() -> Hand {
+    Hand {
+        spec: DeckSpec::standard(),
+        player: 1,
+        session: Fp::from(0x5e55),
+        salt: Fp::from(91),
+        cards: vec![(4, 4), (2, 9)],
+    }
+}
+
+#[test]
+fn test_pile_follows_claims() {
+    // Two claims put down 3 cards, the pile grows claim by claim
+    let cards = [(1, 7, Fp::from(51)), (3, 7, Fp::from(52)), (2, 8, Fp::from(53))];
+    let commitments = card_commitments(&cards);
+
+    let pile = pile_push(empty_pile(), &commitments[..2]);
+    let pile = pile_push(pile, &commitments[2..]);
+    assert_eq!(pile, pile_of(&cards));
+    assert_ne!(pile, pile_of(&[cards[1], cards[0], cards[2]]));
+}
+
+#[test]
+fn test_pick_up_pile() {
+    let cards = [(1, 7, Fp::from(51)), (3, 7, Fp::from(52)), (2, 8, Fp::from(53))];
+    let old = loser_hand();
+    let mut new = old.clone();
+    new.salt = Fp::from(92);
+    new.cards.extend(cards.iter().map(|(suite, rank, _)| (*suite, *rank)));
+
+    assert_eq!(run_pickup(pile_of(&cards), &old, &new, &cards), Ok(()));
+
+    // Leaving a card of the pile behind
+    let mut short = new.clone();
+    short.cards.pop();
+    assert!(run_pickup(pile_of(&cards), &old, &short, &cards).is_err());
+
+    // Picking up a different pile than the public one
+    assert!(run_pickup(pile_of(&cards[..2]), &old, &new, &cards).is_err());
+}
@@ -0,0 +1,209 @@
+use ff::PrimeField;
+use halo2_gadgets::poseidon::{
+    primitives::{self, ConstantLength, P128Pow5T3, Spec},
+    Hash, Pow5Chip, Pow5Config,
+};
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter},
+    pasta::{Fp, Fq},
+    plonk::{Advice, Assigned, Column, ConstraintSystem, Error, Fixed},
+};
+
+// Poseidon over a width 3 state (rate 2, capacity 1) with the audited
+// P128Pow5T3 parameters of halo2_gadgets. Inputs are of a fixed
+// length L, hashed in the ConstantLength<L> domain, so inputs of
+// different lengths never collide. An empty input doesn't compile.
+pub const WIDTH: usize = 3;
+pub const RATE: usize = 2;
+
+// A cell holding a hash input, state word or digest
+pub type Word<F> = AssignedCell<Assigned<F>, F>;
+
+// Fields with P128Pow5T3 parameters, i.e the Pasta fields
+pub trait PoseidonField: PrimeField {
+    type Spec: Spec<Self, WIDTH, RATE>;
+}
+
+impl PoseidonField for Fp {
+    type Spec = P128Pow5T3;
+}
+
+impl PoseidonField for Fq {
+    type Spec = P128Pow5T3;
+}
+
+// Hashes the inputs
+pub fn hash<F: PoseidonField, const L: usize>(inputs: [F; L]) -> F {
+    const { assert!(L > 0, "hash of an empty input") };
+
+    primitives::Hash::<F, F::Spec, ConstantLength<L>, WIDTH, RATE>::init().hash(inputs)
+}
+
+#[derive(Debug, Clone)]
+pub struct PoseidonConfig<F: PoseidonField> {
+    pow5: Pow5Config<F, WIDTH, RATE>,
+    // Holds the field copies of the inputs and digest
+    word: Column<Advice>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PoseidonChip<F: PoseidonField> {
+    config: PoseidonConfig<F>,
+}
+
+impl<F: PoseidonField> PoseidonChip<F> {
+
+    pub fn construct(config: PoseidonConfig<F>) -> Self {
+        Self { config }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>,
+                state: [Column<Advice>; WIDTH], partial_sbox: Column<Advice>,
+                rc_a: [Column<Fixed>; WIDTH], rc_b: [Column<Fixed>; WIDTH],
+                constants: Column<Fixed>) -> PoseidonConfig<F> {
+
+        // The initial capacity is loaded from a constant
+        meta.enable_constant(constants);
+
+        let pow5 = Pow5Chip::configure::<F::Spec>(meta, state, partial_sbox,
+            rc_a, rc_b);
+
+        PoseidonConfig {
+            pow5,
+            word: state[0],
+        }
+    }
+
+    // Hashes the cells the same way as `hash`
+    pub fn hash<const L: usize>(&self, mut layouter: impl Layouter<F>,
+        inputs: &[Word<F>; L]) -> Result<Word<F>, Error> {
+
+        const { assert!(L > 0, "hash of an empty input") };
+
+        // The gadget hashes field cells, copy the inputs into them
+        let message = layouter.assign_region(|| "poseidon inputs", |mut region| {
+            let cells = inputs.iter().enumerate().map(|(i, input)| {
+                let cell = region.assign_advice(|| "input", self.config.word, i,
+                    || input.value().map(|v| v.evaluate()))?;
+                region.constrain_equal(cell.cell(), input.cell())?;
+                Ok(cell)
+            }).collect::<Result<Vec<_>, Error>>()?;
+
+            cells.try_into().map_err(|_| Error::Synthesis)
+        })?;
+
+        let chip = Pow5Chip::construct(self.config.pow5.clone());
+        let hasher = Hash::<_, _, F::Spec, ConstantLength<L>, WIDTH, RATE>::init(
+            chip, layouter.namespace(|| "poseidon init"))?;
+        let digest = hasher.hash(layouter.namespace(|| "poseidon hash"), message)?;
+
+        layouter.assign_region(|| "poseidon digest", |mut region| {
+            let word = region.assign_advice(|| "digest", self.config.word, 0,
+                || digest.value().map(|v| Assigned::from(*v)))?;
+            region.constrain_equal(word.cell(), digest.cell())?;
+            Ok(word)
+        })
+    }
+}
+
+// Allocates the state, round constant and constant columns and
+// configures the chip
+pub(crate) fn configure_poseidon<F: PoseidonField>(meta: &mut ConstraintSystem<F>)
+    -> PoseidonConfig<F> {
+
+    let state = [meta.advice_column(), meta.advice_column(),
+                meta.advice_column()];
+    let partial_sbox = meta.advice_column();
+    let rc_a = [meta.fixed_column(), meta.fixed_column(),
+                meta.fixed_column()];
+    let rc_b = [meta.fixed_column(), meta.fixed_column(),
+                meta.fixed_column()];
+    let constants = meta.fixed_column();
+
+    PoseidonChip::configure(meta, state, partial_sbox, rc_a, rc_b, constants)
+}
+
+#[cfg(test)]
+use halo2_proofs::{
+    circuit::{SimpleFloorPlanner, Value},
+    dev::{MockProver, VerifyFailure},
+    plonk::{Circuit, Instance},
+};
+
+// Hashes its witness inputs and exposes the digest
+#[cfg(test)]
+#[derive(Clone)]
+struct HashCircuit<const L: usize> {
+    inputs: [Value<Assigned<Fp>>; L],
+}
+
+#[cfg(test)]
+impl<const L: usize> Circuit<Fp> for HashCircuit<L> {
+    type Config = (PoseidonConfig<Fp>, Column<Advice>, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { inputs: [Value::unknown(); L] }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> Self::Config {
+        let input = meta.advice_column();
+        let digest = meta.instance_column();
+        meta.enable_equality(input);
+        meta.enable_equality(digest);
+
+        (configure_poseidon(meta), input, digest)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>)
+        -> Result<(), Error> {
+
+        let inputs = layouter.assign_region(|| "inputs", |mut region| {
+            self.inputs.iter().enumerate().map(|(i, v)| {
+                region.assign_advice(|| "input", config.1, i, || *v)
+            }).collect::<Result<Vec<_>, Error>>()
+        })?;
+
+        let chip = PoseidonChip::construct(config.0);
+        let inputs: [Word<Fp>; L] = inputs.try_into().map_err(|_| Error::Synthesis)?;
+        let digest = chip.hash(layouter.namespace(|| "hash"), &inputs)?;
+        layouter.constrain_instance(digest.cell(), config.2, 0)
+    }
+}
+
+#[cfg(test)]
+fn run_hash_circuit<const L: usize>(inputs: [u64; L], digest: Fp)
+    -> Result<(), Vec<VerifyFailure>> {
+
+    let circuit = HashCircuit {
+        inputs: inputs.map(|v| Value::known(Fp::from(v).into())),
+    };
+    MockProver::run(9, &circuit, vec![vec![digest]]).unwrap().verify()
+}
+
+#[cfg(test)]
+fn check_matches_native<const L: usize>() {
+    let inputs: [u64; L] = std::array::from_fn(|i| i as u64 + 1);
+    let digest = hash(inputs.map(Fp::from));
+    assert_eq!(run_hash_circuit(inputs, digest), Ok(()));
+}
+
+#[test]
+fn test_hash_matches_native() {
+    check_matches_native::<1>();
+    check_matches_native::<2>();
+    check_matches_native::<3>();
+    check_matches_native::<4>();
+    check_matches_native::<5>();
+}
+
+#[test]
+fn test_hash_rejects_wrong_digest() {
+    let digest = hash([Fp::from(1), Fp::from(2)]);
+    assert!(run_hash_circuit([1, 3], digest).is_err());
+}
+
+#[test]
+fn test_hash_length_separation() {
+    assert_ne!(hash([Fp::from(1)]), hash([Fp::from(1), Fp::from(0)]));
+}
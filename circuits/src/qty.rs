@@ -1,19 +1,32 @@
 use std::marker::PhantomData;
-use ff::{Field, PrimeField, PrimeFieldBits};
+use std::ops::RangeInclusive;
+use ff::PrimeField;
 
 use halo2_proofs::{
-    circuit::{AssignedCell, Layouter, Value, SimpleFloorPlanner},
+    circuit::{AssignedCell, Layouter, Region, Value},
     plonk::{Advice, Assigned, Column, ConstraintSystem,
-        Constraints, Error, Expression, Selector, Circuit},
+        Error, Expression, Selector, TableColumn},
     poly::Rotation,
+};
+#[cfg(test)]
+use halo2_proofs::{
+    circuit::SimpleFloorPlanner,
+    plonk::Circuit,
     pasta::Fp,
-    dev::{FailureLocation, MockProver, VerifyFailure},    
+    dev::MockProver,
 };
 
+use crate::deck::DeckSpec;
+
+// Quantity of cards [1, 2, 3, 4] per deck
+pub const QTY_MIN: u64 = 1;
+pub const QTY_MAX: u64 = 4;
+
 #[derive(Debug, Clone)]
 pub struct QtyConfig {
     qty: Column<Advice>,
     q_range_check: Selector,
+    table: TableColumn,
 }
 
 #[derive(Debug, Clone)]
@@ -23,14 +36,8 @@ pub struct QtyChip<F:PrimeField> {
 }
 
 #[derive(Debug, Clone)]
-struct QtyConstrained<F: PrimeField>
-    (AssignedCell<Assigned<F>, F>);
-
-trait QtyChipConstrained<F: PrimeField> {
-    fn construct(config: QtyConfig) -> Self;
-    fn configure(meta: &mut ConstraintSystem<F>,
-                suite: Column<Advice>) -> QtyConfig;
-}
+pub struct QtyConstrained<F: PrimeField>
+    (pub AssignedCell<Assigned<F>, F>);
 
 impl<F: PrimeField> QtyChip<F> {
 
@@ -38,56 +45,90 @@ impl<F: PrimeField> QtyChip<F> {
         Self { config, _marker: PhantomData}
     }
 
+    // `q_range_check` must be a complex selector as it is
+    // used in a lookup
     pub fn configure(meta: &mut ConstraintSystem<F>,
                 qty: Column<Advice>, q_range_check: Selector) -> QtyConfig {
 
-        fn card_check<F: PrimeField> (value: Expression<F>) -> Expression<F> {
-            (1..4).fold(value.clone(), |acc, i| {
-                acc * (Expression::Constant(F::from(i as u64)) - value.clone())
-            })        
-        }
-        // qty | selector
-        //   v        s
-        meta.create_gate("qty check",
-            |meta| {
-                let s: Expression<F> = meta.query_selector(q_range_check);
-                let v: Expression<F> = meta.query_advice(
-                        qty, Rotation::cur());
-
-                // Qty check [1, 2, 3, 4]
-                // v * (1 - v) * (2 - v) * ... * (R - 1 - v)
-                //Vec![]
-                let check = card_check(v);
-                Constraints::with_selector(s, Some(("check", check)))
+        Self::configure_range(meta, qty, q_range_check, QTY_MIN)
+    }
+
+    // Range checks `qty` against a table starting at `min`, e.g the
+    // size of a hand which may be 0
+    pub fn configure_range(meta: &mut ConstraintSystem<F>,
+                qty: Column<Advice>, q_range_check: Selector, min: u64) -> QtyConfig {
+
+        let table = meta.lookup_table_column();
+
+        // qty | selector |  table
+        //   v        s      [min..max]
+        meta.lookup(|meta| {
+            let s: Expression<F> = meta.query_selector(q_range_check);
+            let v: Expression<F> = meta.query_advice(qty, Rotation::cur());
+
+            // Qty check [1, 2, 3, 4, ..]. Rows without the selector
+            // look up `min`, so nothing below it is part of the table.
+            let min = Expression::Constant(F::from(min));
+            let one = Expression::Constant(F::ONE);
+            vec![(s.clone() * v + (one - s) * min, table)]
         });
 
         QtyConfig {
-            qty: qty,
+            qty,
             q_range_check,
+            table,
         }
     }
 
+    // Loads the legal quantities of the spec into the lookup table
+    pub fn load_table(&self, layouter: impl Layouter<F>, spec: &DeckSpec)
+        -> Result<(), Error> {
+
+        self.load_range(layouter, QTY_MIN..=spec.qty_max())
+    }
+
+    // Loads the range into the lookup table, it must start at the
+    // `min` the chip was configured with
+    pub fn load_range(&self, mut layouter: impl Layouter<F>, range: RangeInclusive<u64>)
+        -> Result<(), Error> {
+
+        layouter.assign_table(|| "qty table", |mut table| {
+            for (offset, value) in range.clone().enumerate() {
+                table.assign_cell(|| "qty", self.config.table, offset,
+                    || Value::known(F::from(value)))?;
+            }
+            Ok(())
+        })
+    }
+
+    // Assigns and range checks the quantity in an existing region
+    pub fn assign_in_region(&self, region: &mut Region<'_, F>, offset: usize,
+        value: Value<Assigned<F>>) -> Result<QtyConstrained<F>, Error> {
+
+        self.config.q_range_check.enable(region, offset)?;
+
+        region.assign_advice(|| "qty value",
+            self.config.qty, offset, || value)
+            .map(QtyConstrained)
+    }
+
     pub fn assign(&self, mut layouter: impl Layouter<F>,
-        value: Value<Assigned<F>>) -> 
+        value: Value<Assigned<F>>) ->
         Result<QtyConstrained<F>, Error> {
 
-        let offset = 0;
-
         layouter.assign_region( || "Qty", |mut region| {
-            self.config.q_range_check.enable(&mut region, offset)?;
-
-            region.assign_advice(|| "qty value",
-                self.config.qty, offset, || value)
-                .map(QtyConstrained)
+            self.assign_in_region(&mut region, 0, value)
         })
     }
 }
 
+#[cfg(test)]
 #[derive(Default)]
 struct QtyCircuit<F: PrimeField> {
     qty: Value<Assigned<F>>,
 }
 
+#[cfg(test)]
 impl<F: PrimeField> Circuit<F> for QtyCircuit<F> {
 
     type Config = QtyConfig;
@@ -99,32 +140,49 @@ impl<F: PrimeField> Circuit<F> for QtyCircuit<F> {
 
     fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
         let qty = meta.advice_column();
-        let q_range_check = meta.selector();
+        let q_range_check = meta.complex_selector();
 
         QtyChip::configure(meta, qty, q_range_check)
     }
 
-    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) 
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
         -> Result<(), Error> {
 
         let chip: QtyChip<F> = QtyChip::construct(config);
 
-        let _ = chip.assign(layouter.namespace(|| "Qty Assign"),
-            self.qty);
+        chip.load_table(layouter.namespace(|| "Qty Table"), &DeckSpec::standard())?;
+        chip.assign(layouter.namespace(|| "Qty Assign"),
+            self.qty)?;
 
         Ok(())
     }
 }
 
+#[cfg(test)]
+fn qty_circuit(qty: u64) -> QtyCircuit<Fp> {
+    QtyCircuit::<Fp> {
+        qty: Value::known(Fp::from(qty).into()),
+    }
+}
+
 #[test]
 fn test_range_check_1() {
-    const k: u32 = 3;
+    const K: u32 = 4;
 
-    // Successful case
-    let circuit = QtyCircuit::<Fp> {
-        qty: Value::known(Fp::from(0 as u64).into()),
-    };
+    // Successful case, every legal quantity
+    for qty in QTY_MIN..=QTY_MAX {
+        let prover = MockProver::run(K, &qty_circuit(qty), vec![]).unwrap();
+        prover.assert_satisfied();
+    }
+}
 
-    let prover = MockProver::run(k, &circuit, vec![]).unwrap();
-    prover.assert_satisfied();
-}
\ No newline at end of file
+#[test]
+fn test_range_check_2() {
+    const K: u32 = 4;
+
+    // Out of range quantities
+    for qty in [0, 5, 14] {
+        let prover = MockProver::run(K, &qty_circuit(qty), vec![]).unwrap();
+        assert!(prover.verify().is_err(), "qty {} accepted", qty);
+    }
+}
@@ -1,19 +1,29 @@
 use std::marker::PhantomData;
-use ff::{Field, PrimeField, PrimeFieldBits};
+use ff::PrimeField;
 
 use halo2_proofs::{
-    circuit::{AssignedCell, Layouter, Value, SimpleFloorPlanner},
-    plonk::{Advice, Assigned, Column, ConstraintSystem,
-        Constraints, Error, Expression, Selector, Circuit},
+    circuit::{AssignedCell, Layouter, Region, Value},
+    plonk::{Advice, Assigned, Column, ConstraintSystem, Constraints,
+        Error, Expression, Selector, TableColumn},
     poly::Rotation,
+};
+#[cfg(test)]
+use halo2_proofs::{
+    circuit::SimpleFloorPlanner,
+    plonk::{Circuit, Instance},
     pasta::Fp,
-    dev::{FailureLocation, MockProver, VerifyFailure},    
+    dev::MockProver,
 };
 
+// Rank [Ace, 2, 3, 4, 5, 6, 7, 8, 9, 10, J, Q, K]
+pub const RANK_MIN: u64 = 1;
+pub const RANK_MAX: u64 = 13;
+
 #[derive(Debug, Clone)]
 pub struct RankConfig {
     rank: Column<Advice>,
     q_range_check: Selector,
+    table: TableColumn,
 }
 
 #[derive(Debug, Clone)]
@@ -23,14 +33,8 @@ pub struct RankChip<F:PrimeField> {
 }
 
 #[derive(Debug, Clone)]
-struct RankConstrained<F: PrimeField>
-    (AssignedCell<Assigned<F>, F>);
-
-trait RankChipConstrained<F: PrimeField> {
-    fn construct(config: RankConfig) -> Self;
-    fn configure(meta: &mut ConstraintSystem<F>,
-                suite: Column<Advice>) -> RankConfig;
-}
+pub struct RankConstrained<F: PrimeField>
+    (pub AssignedCell<Assigned<F>, F>);
 
 impl<F: PrimeField> RankChip<F> {
 
@@ -38,56 +42,215 @@ impl<F: PrimeField> RankChip<F> {
         Self { config, _marker: PhantomData}
     }
 
+    // `q_range_check` must be a complex selector as it is
+    // used in a lookup
     pub fn configure(meta: &mut ConstraintSystem<F>,
                 rank: Column<Advice>, q_range_check: Selector) -> RankConfig {
 
-        fn rank_check<F: PrimeField> (value: Expression<F>) -> Expression<F> {
-            (1..12).fold(value.clone(), |acc, i| {
-                acc * (Expression::Constant(F::from(i as u64)) - value.clone())
-            })        
-        }
-        // rank | selector
-        //   v        s
-        meta.create_gate("rank check",
-            |meta| {
-                let s: Expression<F> = meta.query_selector(q_range_check);
-                let v: Expression<F> = meta.query_advice(
-                        rank, Rotation::cur());
-
-                // Rank check [Ace, 2, 3, 4, 5, 6, 7, 8, 9, 10, J, Q, K]
-                // v * (1 - v) * (2 - v) * ... * (R - 1 - v)
-                //Vec![]
-                let check = rank_check(v);
-                Constraints::with_selector(s, Some(("check", check)))
+        let table = meta.lookup_table_column();
+
+        // rank | selector |  table
+        //   v        s      [1..13]
+        meta.lookup(|meta| {
+            let s: Expression<F> = meta.query_selector(q_range_check);
+            let v: Expression<F> = meta.query_advice(rank, Rotation::cur());
+
+            // Rank check [Ace, 2, 3, 4, 5, 6, 7, 8, 9, 10, J, Q, K].
+            // Rows without the selector look up RANK_MIN, so 0 is
+            // never part of the table.
+            let min = Expression::Constant(F::from(RANK_MIN));
+            let one = Expression::Constant(F::ONE);
+            vec![(s.clone() * v + (one - s) * min, table)]
         });
 
         RankConfig {
-            rank: rank,
+            rank,
             q_range_check,
+            table,
         }
     }
 
+    // Loads the legal ranks into the lookup table
+    pub fn load_table(&self, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        layouter.assign_table(|| "rank table", |mut table| {
+            for (offset, value) in (RANK_MIN..=RANK_MAX).enumerate() {
+                table.assign_cell(|| "rank", self.config.table, offset,
+                    || Value::known(F::from(value)))?;
+            }
+            Ok(())
+        })
+    }
+
+    // Assigns and range checks the rank in an existing region
+    pub fn assign_in_region(&self, region: &mut Region<'_, F>, offset: usize,
+        value: Value<Assigned<F>>) -> Result<RankConstrained<F>, Error> {
+
+        self.config.q_range_check.enable(region, offset)?;
+
+        region.assign_advice(|| "rank value",
+            self.config.rank, offset, || value)
+            .map(RankConstrained)
+    }
+
+    // Copies and range checks the rank in an existing region. The rank
+    // column must have equality enabled.
+    pub fn copy_in_region(&self, region: &mut Region<'_, F>, offset: usize,
+        cell: &AssignedCell<Assigned<F>, F>) -> Result<RankConstrained<F>, Error> {
+
+        self.config.q_range_check.enable(region, offset)?;
+
+        cell.copy_advice(|| "rank value", region, self.config.rank, offset)
+            .map(RankConstrained)
+    }
+
     pub fn assign(&self, mut layouter: impl Layouter<F>,
-        value: Value<Assigned<F>>) -> 
+        value: Value<Assigned<F>>) ->
         Result<RankConstrained<F>, Error> {
 
-        let offset = 0;
-
         layouter.assign_region( || "Rank", |mut region| {
-            self.config.q_range_check.enable(&mut region, offset)?;
+            self.assign_in_region(&mut region, 0, value)
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RankOrderConfig {
+    rank: RankConfig,
+    wrap: Column<Advice>,
+    s_next: Selector,
+    s_less: Selector,
+}
+
+// Orders ranks, hidden or public: the successor of a rank (King wraps
+// to Ace) and a strict comparison
+#[derive(Debug, Clone)]
+pub struct RankOrderChip<F: PrimeField> {
+    config: RankOrderConfig,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> RankOrderChip<F> {
+
+    pub fn construct(config: RankOrderConfig) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    // Every row of the rank column is range checked, which the gates
+    // rely on
+    pub fn configure(meta: &mut ConstraintSystem<F>, rank: RankConfig) -> RankOrderConfig {
+
+        let wrap = meta.advice_column();
+        let s_next = meta.selector();
+        let s_less = meta.selector();
+
+        meta.enable_equality(rank.rank);
+
+        let one = Expression::Constant(F::ONE);
+        let ranks = Expression::Constant(F::from(RANK_MAX - RANK_MIN + 1));
 
-            region.assign_advice(|| "rank value",
-                self.config.rank, offset, || value)
-                .map(RankConstrained)
+        // | rank | wrap | selector |
+        // |------|------|----------|
+        // |  p   |  w   |  s_next  |
+        // |  r   |      |          |
+        //
+        // r = p + 1 - 13 * w, with p and r in [1..13] w = 1 only for
+        // p = 13 and r = 1
+        meta.create_gate("rank successor", |meta| {
+            let s = meta.query_selector(s_next);
+            let p = meta.query_advice(rank.rank, Rotation::cur());
+            let r = meta.query_advice(rank.rank, Rotation::next());
+            let w = meta.query_advice(wrap, Rotation::cur());
+
+            Constraints::with_selector(s, [
+                ("wrap", w.clone() * (one.clone() - w.clone())),
+                ("next", r - p - one.clone() + ranks * w),
+            ])
+        });
+
+        // | rank | selector |
+        // |------|----------|
+        // |  a   |  s_less  |
+        // |  b   |          |
+        // |  d   |          |
+        //
+        // d = b - a, with d in [1..13] a < b. d can't be 13 as a and b
+        // are in [1..13] too.
+        meta.create_gate("rank less than", |meta| {
+            let s = meta.query_selector(s_less);
+            let a = meta.query_advice(rank.rank, Rotation::cur());
+            let b = meta.query_advice(rank.rank, Rotation::next());
+            let d = meta.query_advice(rank.rank, Rotation(2));
+
+            Constraints::with_selector(s, Some(("less", d - (b - a))))
+        });
+
+        RankOrderConfig {
+            rank,
+            wrap,
+            s_next,
+            s_less,
+        }
+    }
+
+    fn rank(&self) -> RankChip<F> {
+        RankChip::construct(self.config.rank.clone())
+    }
+
+    // Constrains `rank` to follow `prev`
+    pub fn next(&self, mut layouter: impl Layouter<F>,
+        prev: &AssignedCell<Assigned<F>, F>, rank: &AssignedCell<Assigned<F>, F>)
+        -> Result<(), Error> {
+
+        layouter.assign_region(|| "Rank successor", |mut region| {
+            self.config.s_next.enable(&mut region, 0)?;
+
+            let p = self.rank().copy_in_region(&mut region, 0, prev)?.0;
+            self.rank().copy_in_region(&mut region, 1, rank)?;
+
+            let wrap = p.value().map(|p| if p.evaluate() == F::from(RANK_MAX) {
+                Assigned::from(F::ONE)
+            } else {
+                Assigned::from(F::ZERO)
+            });
+            region.assign_advice(|| "wrap", self.config.wrap, 0, || wrap)?;
+
+            Ok(())
         })
     }
+
+    // Constrains `lhs` < `rhs`
+    pub fn less_than(&self, mut layouter: impl Layouter<F>,
+        lhs: &AssignedCell<Assigned<F>, F>, rhs: &AssignedCell<Assigned<F>, F>)
+        -> Result<(), Error> {
+
+        layouter.assign_region(|| "Rank less than", |mut region| {
+            self.config.s_less.enable(&mut region, 0)?;
+
+            let a = self.rank().copy_in_region(&mut region, 0, lhs)?.0;
+            let b = self.rank().copy_in_region(&mut region, 1, rhs)?.0;
+            self.rank().assign_in_region(&mut region, 2,
+                b.value().copied() - a.value().copied())?;
+
+            Ok(())
+        })
+    }
+
+    // Constrains `lhs` > `rhs`
+    pub fn greater_than(&self, layouter: impl Layouter<F>,
+        lhs: &AssignedCell<Assigned<F>, F>, rhs: &AssignedCell<Assigned<F>, F>)
+        -> Result<(), Error> {
+
+        self.less_than(layouter, rhs, lhs)
+    }
 }
 
+#[cfg(test)]
 #[derive(Default)]
 struct RankCircuit<F: PrimeField> {
     rank: Value<Assigned<F>>,
 }
 
+#[cfg(test)]
 impl<F: PrimeField> Circuit<F> for RankCircuit<F> {
 
     type Config = RankConfig;
@@ -98,33 +261,161 @@ impl<F: PrimeField> Circuit<F> for RankCircuit<F> {
     }
 
     fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
-        let card = meta.advice_column();
-        let q_range_check = meta.selector();
+        let rank = meta.advice_column();
+        let q_range_check = meta.complex_selector();
 
-        RankChip::configure(meta, card, q_range_check)
+        RankChip::configure(meta, rank, q_range_check)
     }
 
-    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) 
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
         -> Result<(), Error> {
 
         let chip: RankChip<F> = RankChip::construct(config);
 
-        let _ = chip.assign(layouter.namespace(|| "Rank Assign"),
-            self.rank);
+        chip.load_table(layouter.namespace(|| "Rank Table"))?;
+        chip.assign(layouter.namespace(|| "Rank Assign"),
+            self.rank)?;
 
         Ok(())
     }
 }
 
+#[cfg(test)]
+fn rank_circuit(rank: u64) -> RankCircuit<Fp> {
+    RankCircuit::<Fp> {
+        rank: Value::known(Fp::from(rank).into()),
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default)]
+enum RankOrder {
+    #[default]
+    Next,
+    Less,
+    Greater,
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct RankOrderCircuit<F: PrimeField> {
+    lhs: Value<Assigned<F>>,
+    rhs: Value<Assigned<F>>,
+    order: RankOrder,
+}
+
+// The left rank is hidden, the right one public
+#[cfg(test)]
+impl<F: PrimeField> Circuit<F> for RankOrderCircuit<F> {
+
+    type Config = (RankOrderConfig, Column<Advice>, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { order: self.order, ..Self::default() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let rank = meta.advice_column();
+        let q_range_check = meta.complex_selector();
+        let rank = RankChip::configure(meta, rank, q_range_check);
+
+        let value = meta.advice_column();
+        let instance = meta.instance_column();
+        meta.enable_equality(value);
+        meta.enable_equality(instance);
+
+        (RankOrderChip::configure(meta, rank), value, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let chip: RankOrderChip<F> = RankOrderChip::construct(config.0);
+        chip.rank().load_table(layouter.namespace(|| "Rank Table"))?;
+
+        let (lhs, rhs) = layouter.assign_region(|| "Ranks", |mut region| {
+            Ok((
+                region.assign_advice(|| "lhs", config.1, 0, || self.lhs)?,
+                region.assign_advice(|| "rhs", config.1, 1, || self.rhs)?,
+            ))
+        })?;
+        layouter.constrain_instance(rhs.cell(), config.2, 0)?;
+
+        let layouter = layouter.namespace(|| "Rank order");
+        match self.order {
+            RankOrder::Next => chip.next(layouter, &lhs, &rhs),
+            RankOrder::Less => chip.less_than(layouter, &lhs, &rhs),
+            RankOrder::Greater => chip.greater_than(layouter, &lhs, &rhs),
+        }
+    }
+}
+
+#[cfg(test)]
+fn run_rank_order(lhs: u64, rhs: u64, order: RankOrder)
+    -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 5;
+
+    let circuit = RankOrderCircuit::<Fp> {
+        lhs: Value::known(Fp::from(lhs).into()),
+        rhs: Value::known(Fp::from(rhs).into()),
+        order,
+    };
+    MockProver::run(K, &circuit, vec![vec![Fp::from(rhs)]]).unwrap().verify()
+}
+
 #[test]
 fn test_range_check_1() {
-    const k: u32 = 3;
+    const K: u32 = 5;
 
-    // Successful case
-    let circuit = RankCircuit::<Fp> {
-        rank: Value::known(Fp::from(11 as u64).into()),
-    };
+    // Successful case, every legal rank
+    for rank in RANK_MIN..=RANK_MAX {
+        let prover = MockProver::run(K, &rank_circuit(rank), vec![]).unwrap();
+        prover.assert_satisfied();
+    }
+}
+
+#[test]
+fn test_range_check_2() {
+    const K: u32 = 5;
 
-    let prover = MockProver::run(k, &circuit, vec![]).unwrap();
-    prover.assert_satisfied();
-}
\ No newline at end of file
+    // Out of range ranks
+    for rank in [0, 14] {
+        let prover = MockProver::run(K, &rank_circuit(rank), vec![]).unwrap();
+        assert!(prover.verify().is_err(), "rank {} accepted", rank);
+    }
+}
+
+#[test]
+fn test_rank_successor() {
+    for rank in RANK_MIN..=RANK_MAX {
+        let next = rank % RANK_MAX + 1;
+        assert_eq!(run_rank_order(rank, next, RankOrder::Next), Ok(()));
+
+        // Any other rank, including a successor out of range
+        for other in [rank, rank + 2, rank + 1 + RANK_MAX, 0] {
+            if other != next {
+                assert!(run_rank_order(rank, other, RankOrder::Next).is_err(),
+                    "{} follows {}", other, rank);
+            }
+        }
+    }
+    assert!(run_rank_order(RANK_MAX, RANK_MAX + 1, RankOrder::Next).is_err());
+}
+
+#[test]
+fn test_rank_compare() {
+    assert_eq!(run_rank_order(1, 13, RankOrder::Less), Ok(()));
+    assert_eq!(run_rank_order(6, 7, RankOrder::Less), Ok(()));
+    assert_eq!(run_rank_order(12, 3, RankOrder::Greater), Ok(()));
+
+    for (lhs, rhs) in [(7, 7), (13, 1), (8, 2)] {
+        assert!(run_rank_order(lhs, rhs, RankOrder::Less).is_err());
+        assert!(run_rank_order(rhs, lhs, RankOrder::Greater).is_err());
+    }
+
+    // Out of range ranks compare neither way
+    assert!(run_rank_order(0, 5, RankOrder::Less).is_err());
+    assert!(run_rank_order(5, 14, RankOrder::Less).is_err());
+}
@@ -0,0 +1,247 @@
+use ff::Field;
+use group::GroupEncoding;
+use rand_core::RngCore;
+#[cfg(test)]
+use group::Group;
+#[cfg(test)]
+use rand_core::OsRng;
+
+use crate::mental_poker::{MaskedCard, Point, RemaskProof, Scalar, remask_by, verify_remask};
+#[cfg(test)]
+use crate::deck::standard_deck;
+#[cfg(test)]
+use crate::mental_poker::{card_point, mask, point_card, unmask};
+
+// Verifiable shuffle of a masked deck. The shuffler permutes the cards
+// and re-masks each of them under the table key, so they never see a
+// card face up and nobody else learns the order.
+//
+// The proof cuts and chooses over shadow shuffles of the input deck.
+// The challenge opens each shadow either to the input or to the
+// output, a permutation and a re-masking proof per card. One side
+// reveals nothing of the shuffle, a shuffler that swapped a card
+// fails each round with probability 1/2.
+
+// Shadow shuffles of a proof, a cheat passes with 2^-SHUFFLE_ROUNDS
+pub const SHUFFLE_ROUNDS: usize = 40;
+
+// Domain of the Fiat-Shamir challenge bits
+const SHUFFLE_PERSONAL: &[u8; 16] = b"bbbluff_Shuffle_";
+
+// One bit of the 64 bytes challenge per round
+const _: () = assert!(SHUFFLE_ROUNDS <= 64 * 8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShuffleError {
+    // The output deck does not hold as many cards as the input
+    DeckSize { expected: usize, found: usize },
+    // The proof does not hold SHUFFLE_ROUNDS shadows of the deck
+    Malformed,
+    // The opened side of the shadow does not link the decks
+    InvalidRound(usize),
+}
+
+// Card i of the later deck re-masks card `permutation[i]` of the
+// earlier one
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Opening {
+    permutation: Vec<usize>,
+    proofs: Vec<RemaskProof>,
+}
+
+impl Opening {
+    // Whether `after` re-masks a permutation of `before`
+    fn links(&self, key: &Point, before: &[MaskedCard], after: &[MaskedCard]) -> bool {
+        let mut seen = vec![false; before.len()];
+
+        self.permutation.len() == after.len() && self.proofs.len() == after.len() &&
+            self.permutation.iter()
+                .all(|p| seen.get_mut(*p).is_some_and(|seen| !std::mem::replace(seen, true))) &&
+            after.iter().zip(&self.permutation).zip(&self.proofs)
+                .all(|((card, p), proof)| verify_remask(key, &before[*p], card, proof))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShuffleProof {
+    shadows: Vec<Vec<MaskedCard>>,
+    openings: Vec<Opening>,
+}
+
+// A uniform permutation of n cards (Fisher-Yates)
+fn random_permutation(n: usize, mut rng: impl RngCore) -> Vec<usize> {
+    let mut permutation: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        // The modulo bias is below 2^-50 for any deck
+        let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+        permutation.swap(i, j);
+    }
+
+    permutation
+}
+
+// Card i of the result re-masks card `permutation[i]` of the deck,
+// with the returned mask
+fn permute(key: &Point, deck: &[MaskedCard], permutation: &[usize], mut rng: impl RngCore)
+    -> (Vec<MaskedCard>, Vec<Scalar>) {
+
+    permutation.iter().map(|p| {
+        let r = Scalar::random(&mut rng);
+        (remask_by(key, &deck[*p], &r), r)
+    }).unzip()
+}
+
+// Fiat-Shamir bits over the key, the decks and the shadows. A set bit
+// opens the shadow to the output, a clear one to the input.
+fn challenge_bits(key: &Point, input: &[MaskedCard], output: &[MaskedCard],
+    shadows: &[Vec<MaskedCard>]) -> [bool; SHUFFLE_ROUNDS] {
+
+    let mut state = blake2b_simd::Params::new()
+        .hash_length(64)
+        .personal(SHUFFLE_PERSONAL)
+        .to_state();
+    state.update(&key.to_bytes());
+    for card in input.iter().chain(output).chain(shadows.iter().flatten()) {
+        state.update(&card.c1.to_bytes());
+        state.update(&card.c2.to_bytes());
+    }
+
+    let hash = state.finalize();
+    std::array::from_fn(|round| (hash.as_bytes()[round / 8] >> (round % 8)) & 1 == 1)
+}
+
+// Shuffles and re-masks the deck under the key, with the proof that
+// the result holds the same cards
+pub fn shuffle(key: &Point, deck: &[MaskedCard], mut rng: impl RngCore)
+    -> (Vec<MaskedCard>, ShuffleProof) {
+
+    let permutation = random_permutation(deck.len(), &mut rng);
+    let (output, masks) = permute(key, deck, &permutation, &mut rng);
+
+    let (shadow_permutations, (shadows, shadow_masks)): (Vec<_>, (Vec<_>, Vec<_>)) =
+        (0..SHUFFLE_ROUNDS).map(|_| {
+            let shadow = random_permutation(deck.len(), &mut rng);
+            let cards = permute(key, deck, &shadow, &mut rng);
+            (shadow, cards)
+        }).unzip();
+
+    let bits = challenge_bits(key, deck, &output, &shadows);
+    let openings = shadow_permutations.into_iter().zip(&shadow_masks).zip(bits)
+        .map(|((shadow, shadow_masks), to_output)| if to_output {
+            // Card i of the output re-masks the shadow card that holds
+            // input card permutation[i]
+            let mut position = vec![0; deck.len()];
+            for (k, p) in shadow.iter().enumerate() {
+                position[*p] = k;
+            }
+            let permutation: Vec<usize> = permutation.iter().map(|p| position[*p]).collect();
+            let proofs = permutation.iter().zip(&masks)
+                .map(|(k, r)| RemaskProof::prove(key, &(r - shadow_masks[*k]), &mut rng))
+                .collect();
+
+            Opening { permutation, proofs }
+        } else {
+            let proofs = shadow_masks.iter()
+                .map(|r| RemaskProof::prove(key, r, &mut rng))
+                .collect();
+
+            Opening { permutation: shadow, proofs }
+        })
+        .collect();
+
+    (output, ShuffleProof { shadows, openings })
+}
+
+// Checks that the output deck re-masks a permutation of the input
+pub fn verify_shuffle(key: &Point, input: &[MaskedCard], output: &[MaskedCard],
+    proof: &ShuffleProof) -> Result<(), ShuffleError> {
+
+    if output.len() != input.len() {
+        return Err(ShuffleError::DeckSize { expected: input.len(), found: output.len() });
+    }
+    if proof.shadows.len() != SHUFFLE_ROUNDS || proof.openings.len() != SHUFFLE_ROUNDS ||
+        proof.shadows.iter().any(|shadow| shadow.len() != input.len()) {
+        return Err(ShuffleError::Malformed);
+    }
+
+    let bits = challenge_bits(key, input, output, &proof.shadows);
+    for (round, ((shadow, opening), to_output)) in proof.shadows.iter()
+        .zip(&proof.openings).zip(bits).enumerate() {
+
+        let (before, after) = if to_output { (&shadow[..], output) } else {
+            (input, &shadow[..])
+        };
+        if !opening.links(key, before, after) {
+            return Err(ShuffleError::InvalidRound(round));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn masked_deck(key: &Point, cards: usize) -> Vec<MaskedCard> {
+    standard_deck().iter().take(cards)
+        .map(|(suite, rank)| mask(key, card_point(*suite, *rank), OsRng).0)
+        .collect()
+}
+
+#[test]
+fn test_shuffle() {
+    let secrets: Vec<Scalar> = (0..3).map(|_| Scalar::random(OsRng)).collect();
+    let key = secrets.iter().map(|s| Point::generator() * s).sum();
+
+    // Every player shuffles in turn
+    let mut deck = masked_deck(&key, 8);
+    for _ in &secrets {
+        let (output, proof) = shuffle(&key, &deck, OsRng);
+        assert_eq!(verify_shuffle(&key, &deck, &output, &proof), Ok(()));
+        assert!(output.iter().all(|card| !deck.contains(card)));
+        deck = output;
+    }
+
+    let secret: Scalar = secrets.iter().sum();
+    let mut cards: Vec<(u64, u64)> = deck.iter()
+        .map(|card| point_card(&unmask(&secret, card)).unwrap())
+        .collect();
+    cards.sort();
+    assert_eq!(cards, standard_deck()[..8]);
+}
+
+#[test]
+fn test_shuffle_substituted_card() {
+    let key = Point::generator() * Scalar::random(OsRng);
+    let input = masked_deck(&key, 8);
+    let (mut output, proof) = shuffle(&key, &input, OsRng);
+
+    // A card replaced by a King of Flowers
+    output[3] = mask(&key, card_point(4, 13), OsRng).0;
+    assert!(matches!(verify_shuffle(&key, &input, &output, &proof),
+        Err(ShuffleError::InvalidRound(_))));
+
+    // Or dropped
+    assert_eq!(verify_shuffle(&key, &input, &output[1..], &proof),
+        Err(ShuffleError::DeckSize { expected: 8, found: 7 }));
+}
+
+#[test]
+fn test_shuffle_tampered_proof() {
+    let key = Point::generator() * Scalar::random(OsRng);
+    let input = masked_deck(&key, 8);
+    let (output, proof) = shuffle(&key, &input, OsRng);
+
+    let mut swapped = proof.clone();
+    swapped.openings[0].permutation.swap(0, 1);
+    assert_eq!(verify_shuffle(&key, &input, &output, &swapped),
+        Err(ShuffleError::InvalidRound(0)));
+
+    // A permutation that opens the same card twice
+    let mut repeated = proof.clone();
+    repeated.openings[1].permutation[0] = repeated.openings[1].permutation[1];
+    assert_eq!(verify_shuffle(&key, &input, &output, &repeated),
+        Err(ShuffleError::InvalidRound(1)));
+
+    let mut short = proof;
+    short.shadows.pop();
+    assert_eq!(verify_shuffle(&key, &input, &output, &short), Err(ShuffleError::Malformed));
+}
@@ -1,19 +1,62 @@
 use std::marker::PhantomData;
-use ff::{Field, PrimeField, PrimeFieldBits};
+use ff::PrimeField;
 
 use halo2_proofs::{
-    circuit::{AssignedCell, Layouter, Value, SimpleFloorPlanner},
-    plonk::{Advice, Assigned, Column, ConstraintSystem,
-        Constraints, Error, Expression, Selector, Circuit},
+    circuit::{AssignedCell, Layouter, Region, Value},
+    plonk::{Advice, Assigned, Column, ConstraintSystem, Constraints,
+        Error, Expression, Selector, TableColumn},
     poly::Rotation,
+};
+#[cfg(test)]
+use halo2_proofs::{
+    circuit::SimpleFloorPlanner,
+    plonk::{Circuit, Instance},
     pasta::Fp,
-    dev::{FailureLocation, MockProver, VerifyFailure},    
+    dev::MockProver,
 };
 
+use crate::card::CardError;
+use crate::deck::DeckSpec;
+
+// Suite [1-Hearts, 2-Diamonds, 3-Spades, 4-Flowers]
+pub const SUITE_MIN: u64 = 1;
+pub const SUITE_MAX: u64 = 4;
+pub const SUITES: usize = (SUITE_MAX - SUITE_MIN + 1) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colour {
+    // Hearts and Diamonds
+    Red,
+    // Spades and Flowers
+    Black,
+}
+
+impl Colour {
+    pub fn suites(&self) -> &'static [u64] {
+        match self {
+            Colour::Red => &[1, 2],
+            Colour::Black => &[3, 4],
+        }
+    }
+}
+
+// The set of suites as a mask, 1 for every suite of the set
+pub fn suite_mask<F: PrimeField>(suites: &[u64]) -> Result<[F; SUITES], CardError> {
+    let mut mask = [F::ZERO; SUITES];
+    for suite in suites {
+        if !(SUITE_MIN..=SUITE_MAX).contains(suite) {
+            return Err(CardError::InvalidSuite(*suite));
+        }
+        mask[(suite - SUITE_MIN) as usize] = F::ONE;
+    }
+    Ok(mask)
+}
+
 #[derive(Debug, Clone)]
 pub struct SuiteConfig {
     suite: Column<Advice>,
     q_range_check: Selector,
+    table: TableColumn,
 }
 
 #[derive(Debug, Clone)]
@@ -23,14 +66,8 @@ pub struct SuiteChip<F:PrimeField> {
 }
 
 #[derive(Debug, Clone)]
-struct SuiteConstrained<F: PrimeField>
-    (AssignedCell<Assigned<F>, F>);
-
-trait SuiteChipConstrained<F: PrimeField> {
-    fn construct(config: SuiteConfig) -> Self;
-    fn configure(meta: &mut ConstraintSystem<F>,
-                suite: Column<Advice>) -> SuiteConfig;
-}
+pub struct SuiteConstrained<F: PrimeField>
+    (pub AssignedCell<Assigned<F>, F>);
 
 impl<F: PrimeField> SuiteChip<F> {
 
@@ -38,58 +75,197 @@ impl<F: PrimeField> SuiteChip<F> {
         Self { config, _marker: PhantomData}
     }
 
+    // `q_range_check` must be a complex selector as it is
+    // used in a lookup
     pub fn configure(meta: &mut ConstraintSystem<F>,
                 suite: Column<Advice>, q_range_check: Selector) -> SuiteConfig {
 
-        fn suite_check<F: PrimeField> (value: Expression<F>) -> Expression<F> {
-            //value.clone() * (Expression::Constant(Fp::from(1  as u64)) - value.clone())
-            (1..4).fold(value.clone(), |acc, i| {
-                acc * (Expression::Constant(F::from(i as u64)) - value.clone())
-            })        
-        }
-        // suite | selector
-        //   v        s
-        meta.create_gate("suite check",
-            |meta| {
-                let s: Expression<F> = meta.query_selector(q_range_check);
-                let v: Expression<F> = meta.query_advice(
-                        suite, Rotation::cur());
-
-                // Suite check [1-Hearts, 2-Diamonds, 3-Spades, 4-Flowers]
-                // v * (1 - v) * (2 - v) * ... * (R - 1 - v)
-                //Vec![]
-                let check = suite_check(v);
-                Constraints::with_selector(s, Some(("check", check)))
+        let table = meta.lookup_table_column();
+
+        // suite | selector |  table
+        //   v        s      [1..4], [1..5] with jokers
+        meta.lookup(|meta| {
+            let s: Expression<F> = meta.query_selector(q_range_check);
+            let v: Expression<F> = meta.query_advice(suite, Rotation::cur());
+
+            // Suite check [1-Hearts, 2-Diamonds, 3-Spades, 4-Flowers].
+            // Rows without the selector look up SUITE_MIN, so 0 is
+            // never part of the table.
+            let min = Expression::Constant(F::from(SUITE_MIN));
+            let one = Expression::Constant(F::ONE);
+            vec![(s.clone() * v + (one - s) * min, table)]
         });
 
         SuiteConfig {
-            suite: suite,
+            suite,
             q_range_check,
+            table,
         }
     }
 
+    // Loads the legal suites of the spec into the lookup table
+    pub fn load_table(&self, mut layouter: impl Layouter<F>, spec: &DeckSpec)
+        -> Result<(), Error> {
+
+        layouter.assign_table(|| "suite table", |mut table| {
+            for (offset, value) in (SUITE_MIN..=spec.suite_max()).enumerate() {
+                table.assign_cell(|| "suite", self.config.table, offset,
+                    || Value::known(F::from(value)))?;
+            }
+            Ok(())
+        })
+    }
+
+    // Assigns and range checks the suite in an existing region
+    pub fn assign_in_region(&self, region: &mut Region<'_, F>, offset: usize,
+        value: Value<Assigned<F>>) -> Result<SuiteConstrained<F>, Error> {
+
+        self.config.q_range_check.enable(region, offset)?;
+
+        region.assign_advice(|| "suite value",
+            self.config.suite, offset, || value)
+            .map(SuiteConstrained)
+    }
+
     pub fn assign(&self, mut layouter: impl Layouter<F>,
-        value: Value<Assigned<F>>) -> 
+        value: Value<Assigned<F>>) ->
         Result<SuiteConstrained<F>, Error> {
 
-        let offset = 0;
-
         layouter.assign_region( || "Suite", |mut region| {
-            self.config.q_range_check.enable(&mut region, offset)?;
-
-            region.assign_advice(|| "suite value",
-                self.config.suite, offset, || value)
-                .map(SuiteConstrained)
+            self.assign_in_region(&mut region, 0, value)
         })
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct SuiteSetConfig {
+    suite: Column<Advice>,
+    one_hot: [Column<Advice>; SUITES],
+    mask: [Column<Advice>; SUITES],
+    s_member: Selector,
+}
+
+// Proves a hidden suite belongs to a set of suites, fixed in the
+// circuit (a colour) or public
+#[derive(Debug, Clone)]
+pub struct SuiteSetChip<F: PrimeField> {
+    config: SuiteSetConfig,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> SuiteSetChip<F> {
+
+    pub fn construct(config: SuiteSetConfig) -> Self {
+        Self { config, _marker: PhantomData }
+    }
+
+    pub fn configure(meta: &mut ConstraintSystem<F>) -> SuiteSetConfig {
+
+        let suite = meta.advice_column();
+        let one_hot = [(); SUITES].map(|_| meta.advice_column());
+        let mask = [(); SUITES].map(|_| meta.advice_column());
+        let s_member = meta.selector();
+
+        meta.enable_equality(suite);
+        for column in mask {
+            meta.enable_equality(column);
+        }
+
+        // | suite | e1 .. e4 | m1 .. m4 | selector |
+        // |-------|----------|----------|----------|
+        // |   s   | one hot  |   mask   | s_member |
+        //
+        // The e are the one hot encoding of s, so s is a suite, and
+        // the mask holds the suite: e1*m1 + .. + e4*m4 = 1
+        meta.create_gate("suite set member", |meta| {
+            let sel = meta.query_selector(s_member);
+            let s = meta.query_advice(suite, Rotation::cur());
+            let e = one_hot.map(|column| meta.query_advice(column, Rotation::cur()));
+            let m = mask.map(|column| meta.query_advice(column, Rotation::cur()));
+
+            let one = Expression::Constant(F::ONE);
+            let mut constraints: Vec<(&str, Expression<F>)> = e.iter()
+                .map(|e| ("one hot bit", e.clone() * (one.clone() - e.clone())))
+                .collect();
+
+            let bits = e.iter().fold(Expression::Constant(F::ZERO), |sum, e| sum + e.clone());
+            let value = e.iter().zip(SUITE_MIN..=SUITE_MAX)
+                .fold(Expression::Constant(F::ZERO),
+                    |sum, (e, suite)| sum + e.clone() * Expression::Constant(F::from(suite)));
+            let member = e.iter().zip(m.iter())
+                .fold(Expression::Constant(F::ZERO), |sum, (e, m)| sum + e.clone() * m.clone());
+
+            constraints.push(("one hot", bits - one.clone()));
+            constraints.push(("suite", value - s));
+            constraints.push(("member", member - one));
+
+            Constraints::with_selector(sel, constraints)
+        });
+
+        SuiteSetConfig {
+            suite,
+            one_hot,
+            mask,
+            s_member,
+        }
+    }
+
+    // Constrains the suite to one of the `mask`. Returns the mask
+    // cells, to expose a public set.
+    pub fn member(&self, mut layouter: impl Layouter<F>,
+        suite: &AssignedCell<Assigned<F>, F>, mask: [Value<Assigned<F>>; SUITES])
+        -> Result<[AssignedCell<Assigned<F>, F>; SUITES], Error> {
+
+        layouter.assign_region(|| "Suite set", |mut region| {
+            self.config.s_member.enable(&mut region, 0)?;
+
+            let s = suite.copy_advice(|| "suite", &mut region, self.config.suite, 0)?;
+            for (i, column) in self.config.one_hot.iter().enumerate() {
+                let bit = s.value().map(|s| {
+                    let hot = s.evaluate() == F::from(SUITE_MIN + i as u64);
+                    Assigned::from(F::from(hot as u64))
+                });
+                region.assign_advice(|| "one hot", *column, 0, || bit)?;
+            }
+
+            let cells = self.config.mask.iter().zip(mask)
+                .map(|(column, m)| region.assign_advice(|| "mask", *column, 0, || m))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            cells.try_into().map_err(|_| Error::Synthesis)
+        })
+    }
+
+    // Constrains the suite to one of `suites`, fixed in the circuit
+    pub fn member_of(&self, mut layouter: impl Layouter<F>,
+        suite: &AssignedCell<Assigned<F>, F>, suites: &[u64]) -> Result<(), Error> {
+
+        let mask = suite_mask::<F>(suites).map_err(|_| Error::Synthesis)?;
+        let cells = self.member(layouter.namespace(|| "Fixed set"), suite,
+            mask.map(|m| Value::known(Assigned::from(m))))?;
+
+        layouter.assign_region(|| "Fixed mask", |mut region| {
+            for (cell, m) in cells.iter().zip(mask) {
+                region.constrain_constant(cell.cell(), Assigned::from(m))?;
+            }
+            Ok(())
+        })
+    }
 
+    pub fn colour(&self, layouter: impl Layouter<F>,
+        suite: &AssignedCell<Assigned<F>, F>, colour: Colour) -> Result<(), Error> {
+
+        self.member_of(layouter, suite, colour.suites())
+    }
+}
+
+#[cfg(test)]
 #[derive(Default)]
 struct SuiteCircuit<F: PrimeField> {
     suite: Value<Assigned<F>>,
 }
 
+#[cfg(test)]
 impl<F: PrimeField> Circuit<F> for SuiteCircuit<F> {
 
     type Config = SuiteConfig;
@@ -101,34 +277,157 @@ impl<F: PrimeField> Circuit<F> for SuiteCircuit<F> {
 
     fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
         let suite = meta.advice_column();
-        let q_range_check = meta.selector();
+        let q_range_check = meta.complex_selector();
 
         SuiteChip::configure(meta, suite, q_range_check)
     }
 
-    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) 
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
         -> Result<(), Error> {
 
         let chip: SuiteChip<F> = SuiteChip::construct(config);
 
-        let _ = chip.assign(layouter.namespace(|| "Suite Assign"),
-            self.suite);
+        chip.load_table(layouter.namespace(|| "Suite Table"), &DeckSpec::standard())?;
+        chip.assign(layouter.namespace(|| "Suite Assign"),
+            self.suite)?;
 
         Ok(())
     }
 }
 
+#[cfg(test)]
+fn suite_circuit(suite: u64) -> SuiteCircuit<Fp> {
+    SuiteCircuit::<Fp> {
+        suite: Value::known(Fp::from(suite).into()),
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default)]
+enum SuiteSet {
+    #[default]
+    Public,
+    Colour(Colour),
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct SuiteSetCircuit<F: PrimeField> {
+    suite: Value<Assigned<F>>,
+    mask: [Value<Assigned<F>>; SUITES],
+    set: SuiteSet,
+}
+
+// The suite is hidden, a public set is the public input
+#[cfg(test)]
+impl<F: PrimeField> Circuit<F> for SuiteSetCircuit<F> {
+
+    type Config = (SuiteSetConfig, Column<Advice>, Column<Instance>);
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self { set: self.set, ..Self::default() }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> Self::Config {
+        let suite = meta.advice_column();
+        let instance = meta.instance_column();
+        let constants = meta.fixed_column();
+        meta.enable_equality(suite);
+        meta.enable_equality(instance);
+        meta.enable_constant(constants);
+
+        (SuiteSetChip::configure(meta), suite, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>)
+        -> Result<(), Error> {
+
+        let chip: SuiteSetChip<F> = SuiteSetChip::construct(config.0);
+
+        let suite = layouter.assign_region(|| "Suite", |mut region| {
+            region.assign_advice(|| "suite", config.1, 0, || self.suite)
+        })?;
+
+        match self.set {
+            SuiteSet::Public => {
+                let mask = chip.member(layouter.namespace(|| "Public set"),
+                    &suite, self.mask)?;
+                for (row, cell) in mask.iter().enumerate() {
+                    layouter.constrain_instance(cell.cell(), config.2, row)?;
+                }
+                Ok(())
+            }
+            SuiteSet::Colour(colour) => chip.colour(layouter.namespace(|| "Colour"),
+                &suite, colour),
+        }
+    }
+}
+
+#[cfg(test)]
+fn run_suite_set(suite: u64, suites: &[u64], set: SuiteSet)
+    -> Result<(), Vec<halo2_proofs::dev::VerifyFailure>> {
+
+    const K: u32 = 4;
+
+    let mask = suite_mask::<Fp>(suites).unwrap();
+    let circuit = SuiteSetCircuit::<Fp> {
+        suite: Value::known(Fp::from(suite).into()),
+        mask: mask.map(|m| Value::known(m.into())),
+        set,
+    };
+    MockProver::run(K, &circuit, vec![mask.to_vec()]).unwrap().verify()
+}
+
 #[test]
 fn test_range_check_1() {
-    const k: u32 = 3;
+    const K: u32 = 4;
 
-    // Successful case
-    let circuit = SuiteCircuit::<Fp> {
-        suite: Value::known(Fp::from(0 as u64).into()),
-    };
+    // Successful case, every legal suite
+    for suite in SUITE_MIN..=SUITE_MAX {
+        let prover = MockProver::run(K, &suite_circuit(suite), vec![]).unwrap();
+        prover.assert_satisfied();
+    }
+}
+
+#[test]
+fn test_range_check_2() {
+    const K: u32 = 4;
 
-    let prover = MockProver::run(k, &circuit, vec![]).unwrap();
-    prover.assert_satisfied();
+    // Out of range suites
+    for suite in [0, 5, 14] {
+        let prover = MockProver::run(K, &suite_circuit(suite), vec![]).unwrap();
+        assert!(prover.verify().is_err(), "suite {} accepted", suite);
+    }
+}
+
+#[test]
+fn test_suite_colour() {
+    for colour in [Colour::Red, Colour::Black] {
+        for suite in SUITE_MIN..=SUITE_MAX {
+            let result = run_suite_set(suite, &[], SuiteSet::Colour(colour));
+            assert_eq!(result.is_ok(), colour.suites().contains(&suite),
+                "suite {} {:?}", suite, colour);
+        }
+        assert!(run_suite_set(0, &[], SuiteSet::Colour(colour)).is_err());
+    }
+}
+
+#[test]
+fn test_suite_set() {
+    let sets: [&[u64]; 4] = [&[1], &[2, 4], &[1, 3, 4], &[1, 2, 3, 4]];
+    for suites in sets {
+        for suite in SUITE_MIN..=SUITE_MAX {
+            let result = run_suite_set(suite, suites, SuiteSet::Public);
+            assert_eq!(result.is_ok(), suites.contains(&suite),
+                "suite {} in {:?}", suite, suites);
+        }
+    }
+
+    // Not a suite, even with the full set
+    assert!(run_suite_set(5, &[1, 2, 3, 4], SuiteSet::Public).is_err());
+    assert!(run_suite_set(0, &[1, 2, 3, 4], SuiteSet::Public).is_err());
+    assert!(matches!(suite_mask::<Fp>(&[1, 5]), Err(CardError::InvalidSuite(5))));
 }
 
 #[cfg(feature = "dev-graph")]
@@ -142,10 +441,7 @@ fn print_range_check_1() {
         .titled("Range Check 1 Layout", ("sans-serif", 60))
         .unwrap();
 
-    let circuit = SuiteCircuit::<Fp> {
-        suite: Value::known(Fp::from(0 as u64).into()),
-    };
     halo2_proofs::dev::CircuitLayout::default()
-        .render(3, &circuit, &root)
+        .render(4, &suite_circuit(1), &root)
         .unwrap();
-}
\ No newline at end of file
+}
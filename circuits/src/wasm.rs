@@ -1,13 +1,20 @@
 use std::io::BufReader;
+use crate::dkg::{KeyShare, TableKey, KEY_SHARE_BYTES};
+use crate::envelope::{ProofEnvelope, vk_fingerprint, circuit_fingerprint};
 use crate::card::{create_circuit, empty_circuit,
 	generate_setup_params, generate_keys,
-	generate_proof, verify, create_public_inputs};
+	generate_proof, verify, create_public_inputs, check_vk, CardCircuit,
+	CardError};
+use crate::card;
+use ff::PrimeField;
 use halo2_proofs::{
 	poly::commitment::Params,
 	pasta::{Fp, EqAffine},
-	plonk::keygen_vk
+	plonk::{keygen_vk, ProvingKey, VerifyingKey}
 };
+use group::GroupEncoding;
 use js_sys::Uint8Array;
+use rand_core::OsRng;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
@@ -16,79 +23,265 @@ extern "C" {
 	fn log(s: &str);
 }
 
-fn copy_vec_to_u8arr(v: &Vec<u8>) -> Uint8Array {
+fn copy_vec_to_u8arr(v: &[u8]) -> Uint8Array {
 	let u8_arr = Uint8Array::new_with_length(v.len() as u32);
 	u8_arr.copy_from(v);
 	u8_arr
 }
 
+// Reads a field element from its 32 byte little endian repr
+fn read_fp(bytes: &[u8]) -> Result<Fp, CardError> {
+	let repr: [u8; 32] = bytes.try_into()
+					.map_err(|_| CardError::InvalidFieldElement)?;
+	Option::from(Fp::from_repr(repr))
+		.ok_or(CardError::InvalidFieldElement)
+}
+
+// Thrown to JS as an exception with the error message
+impl From<CardError> for JsValue {
+	fn from(e: CardError) -> Self {
+		JsError::from(e).into()
+	}
+}
+
+fn read_params(param_bytes: &[u8]) -> Result<Params<EqAffine>, CardError> {
+	Ok(Params::<EqAffine>::read(&mut BufReader::new(param_bytes))?)
+}
+
+// Errors are thrown to JS as exceptions with the error message
 #[wasm_bindgen]
-pub fn setup_params(k: u32) -> Uint8Array {
+pub fn setup_params(k: u32) -> Result<Uint8Array, JsError> {
 	log("running setup");
 
 	// Generate setup params
 	let params = generate_setup_params(k);
 	let mut buf = vec![];
-	params.write(&mut buf).expect("Can write params");
+	params.write(&mut buf).map_err(CardError::Params)?;
 
-	copy_vec_to_u8arr(&buf)
+	Ok(copy_vec_to_u8arr(&buf))
 } 
 
 #[wasm_bindgen]
-pub fn proof_generate( qty: u64, suite: u64, rank: u64,
-	param_bytes: &[u8]) -> Uint8Array {
+pub fn card_commitment(qty: u64, suite: u64, rank: u64,
+	salt: &[u8]) -> Result<Uint8Array, JsError> {
 
-	log("proving..");
+	let commitment = create_public_inputs(qty, suite, rank, read_fp(salt)?)?[0];
+
+	Ok(copy_vec_to_u8arr(&commitment.to_repr()))
+}
+
+// Proves cards with params and a proving key that are read and
+// generated once
+#[wasm_bindgen]
+pub struct CardProver {
+	params: Params<EqAffine>,
+	pk: ProvingKey<EqAffine>,
+}
+
+#[wasm_bindgen]
+impl CardProver {
+	#[wasm_bindgen(constructor)]
+	pub fn new(param_bytes: &[u8]) -> Result<CardProver, CardError> {
+		let params = read_params(param_bytes)?;
+
+		// Generate proving key
+		let empty_circuit = empty_circuit();
+		let (pk, _vk) = generate_keys::<Fp>(&params, &empty_circuit)?;
+
+		Ok(CardProver { params, pk })
+	}
 
-	// Read params
-	let params = Params::<EqAffine>::read(
-					&mut BufReader::new(param_bytes)).
-					expect("Failed to read params");
+	// Fingerprint of the vk the proofs are generated under
+	pub fn vk_fingerprint(&self) -> Vec<u8> {
+		vk_fingerprint(self.pk.get_vk()).to_vec()
+	}
 
-	// Create public inputs
-	let public_inputs = create_public_inputs(qty, suite, rank);
+	// Proves the card behind the commitment to (qty, suite, rank, salt),
+	// returns the proof envelope bytes
+	pub fn prove(&self, qty: u64, suite: u64, rank: u64, salt: &[u8])
+		-> Result<Vec<u8>, CardError> {
 
-	// Generate proving key
-	let empty_circuit = empty_circuit();
-	let (pk, _vk) = generate_keys::<Fp>(&params, &empty_circuit);
+		// Create public inputs
+		let salt = read_fp(salt)?;
+		let public_inputs = create_public_inputs(qty, suite, rank, salt)?;
 
-	// Generate proof
-	let card_circuit = create_circuit(qty, suite, rank);
-	let proof = generate_proof(&params, &pk, card_circuit,
-					(&public_inputs.0, &public_inputs.1,
-					 &public_inputs.2));
+		// Generate proof
+		let card_circuit = create_circuit(qty, suite, rank, salt)?;
+		let envelope = generate_proof(&self.params, &self.pk, card_circuit,
+						&public_inputs)?;
 
-	copy_vec_to_u8arr(&proof)
+		Ok(envelope.to_bytes()?)
+	}
 }
 
+// Verifies card proofs with params and a verifying key that are read
+// and generated once
 #[wasm_bindgen]
-pub fn proof_verify(param_bytes: &[u8], qty: u64, suite: u64,
-			rank: u64, proof: &[u8]) -> bool {
+pub struct CardVerifier {
+	params: Params<EqAffine>,
+	vk: VerifyingKey<EqAffine>,
+}
+
+#[wasm_bindgen]
+impl CardVerifier {
+	#[wasm_bindgen(constructor)]
+	pub fn new(param_bytes: &[u8]) -> Result<CardVerifier, CardError> {
+		let params = read_params(param_bytes)?;
+
+		// Generate verifying key
+		let empty_circuit = empty_circuit();
+		let vk = keygen_vk(&params, &empty_circuit)
+			.map_err(CardError::Keygen)?;
+
+		Ok(CardVerifier { params, vk })
+	}
+
+	// Builds the verifier only if its vk is the pinned one, e.g of
+	// the deployed prover
+	pub fn pinned(param_bytes: &[u8], fingerprint: &[u8])
+		-> Result<CardVerifier, CardError> {
+
+		let verifier = CardVerifier::new(param_bytes)?;
+		check_vk(&verifier.vk, fingerprint)?;
+
+		Ok(verifier)
+	}
+
+	pub fn vk_fingerprint(&self) -> Vec<u8> {
+		vk_fingerprint(&self.vk).to_vec()
+	}
+
+	// Returns false if the proof does not verify, errors on malformed
+	// inputs
+	pub fn verify(&self, commitment: &[u8], proof: &[u8])
+		-> Result<bool, CardError> {
+
+		// The card commitment is the only public input
+		let public_inputs = vec![read_fp(commitment)?];
+		let envelope = ProofEnvelope::from_bytes(proof)?;
+
+		// Verify the proof and public input
+		match verify(&self.params, &self.vk, &public_inputs, &envelope) {
+			Ok(()) => Ok(true),
+			Err(CardError::Verification(_)) => Ok(false),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+#[wasm_bindgen]
+pub fn proof_generate( qty: u64, suite: u64, rank: u64, salt: &[u8],
+	param_bytes: &[u8]) -> Result<Uint8Array, JsError> {
+
+	log("proving..");
+
+	let proof = CardProver::new(param_bytes)?
+					.prove(qty, suite, rank, salt)?;
+
+	Ok(copy_vec_to_u8arr(&proof))
+}
+
+// Returns false if the proof does not verify, throws on malformed
+// inputs
+#[wasm_bindgen]
+pub fn proof_verify(param_bytes: &[u8], commitment: &[u8],
+			proof: &[u8]) -> Result<bool, JsError> {
 
 	log("verifying..");
 
-	let params = Params::<EqAffine>::read(
-					&mut BufReader::new(param_bytes)).
-					expect("Failed to read params");
+	Ok(CardVerifier::new(param_bytes)?.verify(commitment, proof)?)
+}
+
+// Fingerprint of the card circuit vk under the params
+#[wasm_bindgen]
+pub fn card_vk_fingerprint(param_bytes: &[u8]) -> Result<Vec<u8>, CardError> {
+	Ok(card::card_vk_fingerprint(&read_params(param_bytes)?)?.to_vec())
+}
+
+// Fingerprint of the card constraint system, the same for every k
+#[wasm_bindgen]
+pub fn card_circuit_fingerprint() -> Vec<u8> {
+	circuit_fingerprint::<CardCircuit<Fp>>().to_vec()
+}
+
+// Reencodes the proof envelope bytes as JSON
+#[wasm_bindgen]
+pub fn proof_json(proof: &[u8]) -> Result<String, CardError> {
+	Ok(ProofEnvelope::from_bytes(proof)?.to_json()?)
+}
+
+// Picks the key share of `player` for the table, returns the secret
+// share (32 bytes) followed by the public key share
+#[wasm_bindgen]
+pub fn dkg_key_share(player: u32, session: &[u8]) -> Uint8Array {
+	let (secret, share) = KeyShare::generate(player, session, OsRng);
+
+	let mut buf = secret.to_repr().to_vec();
+	buf.extend_from_slice(&share.to_bytes());
+
+	copy_vec_to_u8arr(&buf)
+}
+
+// Checks the concatenated key shares of every player and returns
+// the table key
+#[wasm_bindgen]
+pub fn dkg_table_key(session: &[u8], shares: &[u8]) -> Result<Uint8Array, JsError> {
+	let shares: Vec<KeyShare> = shares.chunks(KEY_SHARE_BYTES)
+		.map(KeyShare::from_bytes)
+		.collect::<Result<_, _>>()
+		.map_err(CardError::Dkg)?;
+
+	let table = TableKey::aggregate(session, &shares)
+		.map_err(CardError::Dkg)?;
+
+	Ok(copy_vec_to_u8arr(&table.key().to_bytes()))
+}
+
+#[cfg(test)]
+use crate::envelope::EnvelopeError;
+
+#[test]
+fn test_prover_verifier() {
+	let mut param_bytes = vec![];
+	generate_setup_params(8).write(&mut param_bytes).unwrap();
+
+	let prover = CardProver::new(&param_bytes).unwrap();
+	let verifier = CardVerifier::new(&param_bytes).unwrap();
+
+	let salt = Fp::from(0x5eed_u64);
+	let commitment = create_public_inputs(3, 3, 11, salt).unwrap()[0];
+
+	// Both handles are reused across cards
+	for (qty, suite, rank) in [(3, 3, 11), (1, 4, 2)] {
+		let proof = prover.prove(qty, suite, rank, &salt.to_repr()).unwrap();
+		let commitment = create_public_inputs(qty, suite, rank, salt).unwrap()[0];
+		assert!(verifier.verify(&commitment.to_repr(), &proof).unwrap());
+	}
 
-	// Generate verifying key
-	let empty_circuit = empty_circuit();
-	let vk = keygen_vk(&params, &empty_circuit)
-		.expect("Failed to generate verifying key");
+	// A proof of another card is rejected by its envelope, a tampered
+	// proof doesn't verify
+	let proof = prover.prove(1, 4, 2, &salt.to_repr()).unwrap();
+	assert!(matches!(verifier.verify(&commitment.to_repr(), &proof),
+		Err(CardError::Envelope(EnvelopeError::PublicInputsMismatch))));
 
-	// Create public inputs
-	let public_inputs = create_public_inputs(qty, suite, rank);
+	let mut envelope = ProofEnvelope::from_bytes(&proof).unwrap();
+	envelope.public_inputs = vec![commitment];
+	assert!(!verifier.verify(&commitment.to_repr(), &envelope.to_bytes().unwrap()).unwrap());
 
-	// Trasform proof to vector
-	let proof_vec = proof.to_vec();
+	assert!(matches!(prover.prove(3, 3, 14, &salt.to_repr()),
+		Err(CardError::InvalidCard { .. })));
+	assert!(matches!(verifier.verify(&[0xff; 32], &[]),
+		Err(CardError::InvalidFieldElement)));
+	assert!(matches!(verifier.verify(&commitment.to_repr(), &[]),
+		Err(CardError::Envelope(_))));
 
-	// Verify the proof and public input
-    let ret = verify(&params, &vk, (&public_inputs.0, &public_inputs.1,
-                        &public_inputs.2), proof_vec);
+	// Verifiers pinned to the prover's vk
+	let fingerprint = prover.vk_fingerprint();
+	assert_eq!(verifier.vk_fingerprint(), fingerprint);
+	assert!(CardVerifier::pinned(&param_bytes, &fingerprint).is_ok());
 
-    match ret {
-    	Err(_) => false,
-    	_ => true,
-    }
+	let mut other_bytes = vec![];
+	generate_setup_params(9).write(&mut other_bytes).unwrap();
+	assert!(matches!(CardVerifier::pinned(&other_bytes, &fingerprint),
+		Err(CardError::Envelope(EnvelopeError::VkMismatch { .. }))));
 }
-	
\ No newline at end of file
@@ -5,43 +5,73 @@ pragma solidity ^0.8.0;
 import "@openzeppelin/contracts/token/ERC1155/ERC1155.sol";
 import "hardhat/console.sol";
 
+// Verifier of the deck proof, i.e a DeckCircuit proof (see
+// circuits/src/deck.rs) that the card commitments are a full deck and
+// the leaves of the root. Its public inputs are the root followed by
+// the commitments in deck order.
+interface IDeckVerifier {
+	function verifyDeck (uint256 _deckroot, uint256 [] calldata _leaves,
+		bytes calldata _deckproof) external view returns (bool);
+}
+
 // Contract for a deck of cards with individual proofs
 contract CardDeck is ERC1155 {
 
-	// Number of cards in the deck
-	uint8 public count;
+	// Number of cards minted
+	uint16 public count;
+
+	// Number of cards in the deck, 52 per deck plus the jokers (see
+	// DeckSpec in circuits/src/deck.rs). Up to 255 decks fit.
+	uint16 public size;
 
-	// An array sequence id vs card token id
+	// The card commitment of each sequence id, also its token id
 	uint256 [] public cards;
 
+	// Merkle root over the cards, leaf _seqid is cards[_seqid]. The
+	// deck proof binds it to the stored commitments.
+	uint256 public root;
+
 	// Creator
 	address public _admin;
 
-	constructor() ERC1155 ("") {
+	// Checks the deck proof before the deck is sealed
+	IDeckVerifier public verifier;
+
+	constructor(IDeckVerifier _verifier, uint8 _decks, uint8 _jokers) ERC1155 ("") {
+		require(_decks > 0);
+		require(address(_verifier) != address(0));
 		_admin = msg.sender;
+		verifier = _verifier;
 		count = 0;
+		size = 52 * uint16(_decks) + _jokers;
+		cards = new uint256[](size);
 	}
 
-	// Mint each card by supplying correct proof
-	function mintCard (uint8 _seqid, uint256 _proofhash) public
+	// Mint each card from its commitment (see commit_deck_card in
+	// circuits/src/deck.rs)
+	function mintCard (uint16 _seqid, uint256 _commitment) public
 		onlyAdmin onlyNotAllMinted onlyValidSeqId (_seqid) {
 
-		cards[_seqid] = _proofhash;
+		cards[_seqid] = _commitment;
 
-		// mint the card ERC1155 token, token id is proofhash  
-		_mint(msg.sender, _proofhash, 1, "");
+		// mint the card ERC1155 token, token id is the commitment
+		_mint(msg.sender, _commitment, 1, "");
 
 		count++;
 	}
 
-	// Seal the card deck once shuffled
-	function sealDeck (uint256 _deckproof) public
+	// Seal the card deck once shuffled, _deckroot is the root of
+	// the deck tree (see circuits/src/deck.rs) and _deckproof proves
+	// the minted commitments are its leaves and a full deck
+	function sealDeck (uint256 _deckroot, bytes calldata _deckproof) public
 		onlyAdmin onlyAllMinted
 		returns (bool success) {
 
 		success = false;
 
-		// Check for _deckproof
+		// Check for _deckproof over the stored cards
+		if (!verifier.verifyDeck(_deckroot, cards, _deckproof)) revert();
+		root = _deckroot;
 		success = true;
 		return success;
 	}
@@ -52,7 +82,7 @@ contract CardDeck is ERC1155 {
 		returns (uint256 [] memory _cards) {
 
 		// Query balance of admin/creator
-		for (uint8 i = 0; i < count; i++) {
+		for (uint16 i = 0; i < count; i++) {
 			_cards[i] = balanceOf(_admin, cards[i]);
 		}
 
@@ -65,17 +95,17 @@ contract CardDeck is ERC1155 {
 	}
 
 	modifier onlyNotAllMinted () {
-		if (!(count < 52)) revert();
+		if (!(count < size)) revert();
 		_;
 	}
 
 	modifier onlyAllMinted () {
-		if (count != 52) revert();
+		if (count != size) revert();
 		_;
 	}
 
-	modifier onlyValidSeqId (uint8 _id) {
-		if ((_id < 0) || (_id > 51)) revert();
+	modifier onlyValidSeqId (uint16 _id) {
+		if (_id >= size) revert();
 		_;
 	}
 
@@ -9,11 +9,12 @@ class CardDeck {
 	}
 }
 
-// Deploys the CardDeck contract
-CardDeck.prototype.deploy = async function () {
+// Deploys the CardDeck contract checking deck proofs with the verifier
+// at `verifier`, by default a single deck without jokers
+CardDeck.prototype.deploy = async function (verifier, decks = 1, jokers = 0) {
 
 	let _carddeck = await hre.ethers.getContractFactory('CardDeck');
-	this.carddeck = await _carddeck.deploy();
+	this.carddeck = await _carddeck.deploy(verifier, decks, jokers);
 	await this.carddeck.waitForDeployment();
 	const _address = await this.carddeck.getAddress();
 	console.log(`Deployed to ${_address}`);
@@ -26,22 +27,22 @@ CardDeck.prototype.deploy = async function () {
 	this.mint();
 }
 
-// Mints a single card
+// Mints cards from their commitments
 CardDeck.prototype.mint = async function () {
 
 	let _id = 1;
-	let _proof = "0x21373022272527af1283e01282b202d";
+	let _commitment = "0x21373022272527af1283e01282b202d";
 
 	console.log("Minting a card..");
-	await this.carddeck.connect(this.signer).mint(_id, _proof);
+	await this.carddeck.connect(this.signer).mintCard(_id, _commitment);
 
 	console.log("Minted a card");
 
 	_id = 2;
-	_proof = "0x21373022272527af3434847ef2d";
+	_commitment = "0x21373022272527af3434847ef2d";
 
 	console.log("Minting a card..");
-	await this.carddeck.connect(this.signer).mint(_id, _proof);
+	await this.carddeck.connect(this.signer).mintCard(_id, _commitment);
 
 	console.log("Minted a card");
 
@@ -53,7 +54,16 @@ CardDeck.prototype.mint = async function () {
 
 var carddecknet = new CardDeck();
 
-carddecknet.deploy().catch((error) => {
-	console.error(error);
+// The address of a deployed IDeckVerifier, the deck can't be sealed
+// without one
+const verifier = process.env.DECK_VERIFIER;
+
+if (!verifier || !hre.ethers.isAddress(verifier)) {
+	console.error("DECK_VERIFIER must be the address of the deck verifier");
 	process.exitCode = 1;
-})
\ No newline at end of file
+} else {
+	carddecknet.deploy(verifier).catch((error) => {
+		console.error(error);
+		process.exitCode = 1;
+	})
+}
\ No newline at end of file